@@ -0,0 +1,15 @@
+CREATE TABLE scrape_run
+(
+    id              VARCHAR NOT NULL PRIMARY KEY,
+    series          VARCHAR NOT NULL,
+    trigger         VARCHAR NOT NULL,
+    started_at      BIGINT  NOT NULL,
+    finished_at     BIGINT,
+    stored          BIGINT  NOT NULL,
+    already_present BIGINT  NOT NULL,
+    failed          BIGINT  NOT NULL,
+    error           VARCHAR,
+    errors_json     jsonb   NOT NULL
+);
+
+CREATE INDEX scrape_run_started_at_idx ON scrape_run (started_at DESC)
@@ -0,0 +1,7 @@
+CREATE TABLE solve_progress
+(
+    crossword_for VARCHAR NOT NULL,
+    team_for      VARCHAR NOT NULL,
+    completed_at  BIGINT,
+    PRIMARY KEY (crossword_for, team_for)
+)
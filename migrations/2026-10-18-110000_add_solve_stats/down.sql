@@ -0,0 +1,5 @@
+ALTER TABLE solve_progress
+    DROP COLUMN first_move_at,
+    DROP COLUMN active_millis,
+    DROP COLUMN reveals,
+    DROP COLUMN checks;
@@ -0,0 +1,5 @@
+ALTER TABLE solve_progress
+    ADD COLUMN first_move_at BIGINT,
+    ADD COLUMN active_millis BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN reveals       BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN checks        BIGINT NOT NULL DEFAULT 0
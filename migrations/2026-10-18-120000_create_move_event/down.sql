@@ -0,0 +1,4 @@
+ALTER TABLE solution
+    DROP COLUMN snapshot_seq;
+
+DROP TABLE move_event;
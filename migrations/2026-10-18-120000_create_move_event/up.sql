@@ -0,0 +1,17 @@
+CREATE TABLE move_event
+(
+    crossword_for VARCHAR NOT NULL,
+    team_for      VARCHAR NOT NULL,
+    seq           BIGINT  NOT NULL,
+    user_for      VARCHAR NOT NULL,
+    x             BIGINT  NOT NULL,
+    y             BIGINT  NOT NULL,
+    value         VARCHAR NOT NULL,
+    revealed      BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at    BIGINT  NOT NULL,
+    PRIMARY KEY (crossword_for, team_for, seq)
+);
+
+-- solution_json becomes a snapshot of the moves up to snapshot_seq, existing solutions are snapshots of no moves
+ALTER TABLE solution
+    ADD COLUMN snapshot_seq BIGINT NOT NULL DEFAULT 0
@@ -0,0 +1,4 @@
+ALTER TABLE move_event
+    DROP COLUMN group_seq,
+    DROP COLUMN actor,
+    DROP COLUMN reverts;
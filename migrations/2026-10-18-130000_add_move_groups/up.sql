@@ -0,0 +1,20 @@
+-- moves sent together form a group, numbered by their first move, which can be undone and redone by the user who made them
+ALTER TABLE move_event
+    ADD COLUMN group_seq BIGINT,
+    ADD COLUMN actor     VARCHAR,
+    ADD COLUMN reverts   BIGINT;
+
+UPDATE move_event
+SET group_seq = groups.first_seq,
+    actor     = move_event.user_for
+FROM (SELECT crossword_for, team_for, user_for, created_at, MIN(seq) AS first_seq
+      FROM move_event
+      GROUP BY crossword_for, team_for, user_for, created_at) AS groups
+WHERE move_event.crossword_for = groups.crossword_for
+  AND move_event.team_for = groups.team_for
+  AND move_event.user_for = groups.user_for
+  AND move_event.created_at = groups.created_at;
+
+ALTER TABLE move_event
+    ALTER COLUMN group_seq SET NOT NULL,
+    ALTER COLUMN actor SET NOT NULL
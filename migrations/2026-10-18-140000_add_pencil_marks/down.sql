@@ -0,0 +1,3 @@
+ALTER TABLE move_event
+    DROP COLUMN mark,
+    DROP COLUMN candidates;
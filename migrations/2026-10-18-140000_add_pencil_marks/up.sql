@@ -0,0 +1,3 @@
+ALTER TABLE move_event
+    ADD COLUMN mark       VARCHAR NOT NULL DEFAULT 'pen',
+    ADD COLUMN candidates TEXT[]  NOT NULL DEFAULT '{}'
@@ -0,0 +1,12 @@
+CREATE TABLE clue_note
+(
+    crossword_for VARCHAR NOT NULL,
+    team_for      VARCHAR NOT NULL,
+    number        BIGINT  NOT NULL,
+    direction     VARCHAR NOT NULL,
+    text          VARCHAR NOT NULL,
+    highlights    JSONB   NOT NULL,
+    modified_by   VARCHAR NOT NULL,
+    modified_at   BIGINT  NOT NULL,
+    PRIMARY KEY (crossword_for, team_for, number, direction)
+)
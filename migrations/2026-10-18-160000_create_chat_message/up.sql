@@ -0,0 +1,13 @@
+CREATE TABLE chat_message
+(
+    id             VARCHAR PRIMARY KEY,
+    crossword_for  VARCHAR NOT NULL,
+    team_for       VARCHAR NOT NULL,
+    user_for       VARCHAR NOT NULL,
+    text           VARCHAR NOT NULL,
+    clue_number    BIGINT,
+    clue_direction VARCHAR,
+    sent_at        BIGINT  NOT NULL
+);
+
+CREATE INDEX chat_message_team_crossword ON chat_message (team_for, crossword_for, sent_at)
@@ -0,0 +1,3 @@
+DROP INDEX move_event_cells;
+
+DROP INDEX move_event_actor_groups
@@ -0,0 +1,4 @@
+-- finds a user's move groups for undo and redo, and the moves before a group in the cells it changed
+CREATE INDEX move_event_actor_groups ON move_event (crossword_for, team_for, actor, group_seq);
+
+CREATE INDEX move_event_cells ON move_event (crossword_for, team_for, x, y, seq)
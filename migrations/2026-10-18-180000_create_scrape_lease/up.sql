@@ -0,0 +1,7 @@
+-- which scrape holds each series, lapsing at expires_at unless renewed so a scrape that dies only blocks its series briefly
+CREATE TABLE scrape_lease
+(
+    series     VARCHAR PRIMARY KEY,
+    holder     VARCHAR NOT NULL,
+    expires_at BIGINT  NOT NULL
+)
@@ -0,0 +1,67 @@
+use actix_web::web::Data;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::services::crossword_service::backfill_crosswords;
+use crate::services::crossword_source::CrosswordSource;
+use crate::services::import_service::import_xd_directory;
+use crate::DbPool;
+
+const USAGE: &str =
+    "Usage: cooperative-crosswords backfill <series> <from series_no> <to series_no>
+       cooperative-crosswords import-xd <series> <directory>";
+
+/// Runs an admin command given on the command line instead of starting the server
+pub async fn run(
+    pool: DbPool,
+    source: Arc<dyn CrosswordSource>,
+    args: &[String],
+) -> std::io::Result<()> {
+    match args.first().map(|command| command.as_str()) {
+        Some("backfill") => backfill(pool, source, &args[1..]).await,
+        Some("import-xd") => import_xd(pool, &args[1..]).await,
+        _ => Err(usage_error()),
+    }
+}
+
+async fn backfill(
+    pool: DbPool,
+    source: Arc<dyn CrosswordSource>,
+    args: &[String],
+) -> std::io::Result<()> {
+    let (series, from, to) = match args {
+        [series, from, to] => (series, parse_series_no(from)?, parse_series_no(to)?),
+        _ => return Err(usage_error()),
+    };
+    let report = backfill_crosswords(Data::new(pool), source, series.to_lowercase(), from, to)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+async fn import_xd(pool: DbPool, args: &[String]) -> std::io::Result<()> {
+    let (series, directory) = match args {
+        [series, directory] => (series, directory),
+        _ => return Err(usage_error()),
+    };
+    let report = import_xd_directory(Data::new(pool), series.clone(), Path::new(directory))
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn parse_series_no(value: &str) -> std::io::Result<i64> {
+    value.parse::<i64>().map_err(|e| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid series_no {}: {}", value, e),
+        )
+    })
+}
+
+fn usage_error() -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidInput, USAGE)
+}
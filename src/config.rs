@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use chrono_tz::Tz;
+
+pub const DEFAULT_SERIES: &str = "cryptic";
+
+/// Every crossword series the Guardian publishes, whether or not it is configured to be scraped
+pub const GUARDIAN_SERIES: &[&str] = &[
+    "cryptic", "quick", "quiptic", "prize", "everyman", "speedy", "weekend",
+];
+
+/// How the Guardian is scraped, each field is read from an optional env variable
+#[derive(Debug, Clone)]
+pub struct ScraperConfig {
+    /// `GUARDIAN_BASE_URL`, defaults to `https://www.theguardian.com`
+    pub base_url: String,
+    /// `SCRAPER_USER_AGENT`
+    pub user_agent: String,
+    /// `SCRAPER_TIMEOUT_SECONDS`, the timeout for a whole request
+    pub timeout: Duration,
+    /// `SCRAPER_CONNECT_TIMEOUT_SECONDS`
+    pub connect_timeout: Duration,
+    /// `SCRAPER_PROXY`, a proxy URL all requests are sent through
+    pub proxy: Option<String>,
+    /// `SCRAPER_CONCURRENCY`, how many crosswords are scraped at once
+    pub concurrency: usize,
+    /// `SCRAPER_REQUESTS_PER_SECOND`, the most requests sent to a host each second, above zero
+    pub requests_per_second: f64,
+    /// `SCRAPER_MAX_RETRIES`, retries of rate limited, failed or timed out requests
+    pub max_retries: u32,
+    /// `SCRAPER_BACKOFF_BASE_MILLIS`, the delay before the first retry, doubled for each retry
+    pub backoff_base: Duration,
+    /// `SCRAPER_BACKOFF_MAX_SECONDS`, the longest delay between retries, a longer `Retry-After`
+    /// fails the request
+    pub backoff_max: Duration,
+}
+
+pub fn scraper_config() -> Result<ScraperConfig, String> {
+    let requests_per_second: f64 = number_from_env("SCRAPER_REQUESTS_PER_SECOND", 2.0)?;
+    if !requests_per_second.is_finite() || requests_per_second <= 0.0 {
+        return Err(format!(
+            "Invalid SCRAPER_REQUESTS_PER_SECOND: {}, it must be above zero",
+            requests_per_second
+        ));
+    }
+    if Duration::try_from_secs_f64(1.0 / requests_per_second).is_err() {
+        return Err(format!(
+            "Invalid SCRAPER_REQUESTS_PER_SECOND: {:e} is too small",
+            requests_per_second
+        ));
+    }
+    Ok(ScraperConfig {
+        base_url: std::env::var("GUARDIAN_BASE_URL")
+            .unwrap_or("https://www.theguardian.com".to_string()),
+        user_agent: std::env::var("SCRAPER_USER_AGENT").unwrap_or(format!(
+            "{}/{}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )),
+        timeout: seconds_from_env("SCRAPER_TIMEOUT_SECONDS", 30)?,
+        connect_timeout: seconds_from_env("SCRAPER_CONNECT_TIMEOUT_SECONDS", 10)?,
+        proxy: std::env::var("SCRAPER_PROXY")
+            .ok()
+            .filter(|proxy| !proxy.is_empty()),
+        concurrency: number_from_env("SCRAPER_CONCURRENCY", 4)?.max(1),
+        requests_per_second,
+        max_retries: number_from_env("SCRAPER_MAX_RETRIES", 3)?,
+        backoff_base: Duration::from_millis(number_from_env("SCRAPER_BACKOFF_BASE_MILLIS", 500)?),
+        backoff_max: seconds_from_env("SCRAPER_BACKOFF_MAX_SECONDS", 60)?,
+    })
+}
+
+fn number_from_env<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    std::env::var(name)
+        .map_or(Ok(default), |value| value.parse())
+        .map_err(|e| format!("Invalid {}: {}", name, e))
+}
+
+fn seconds_from_env(name: &str, default: u64) -> Result<Duration, String> {
+    number_from_env(name, default).map(Duration::from_secs)
+}
+
+/// Series scraped by `update_crosswords`, read from the comma separated `CROSSWORD_SERIES`
+/// env variable, eg. `CROSSWORD_SERIES=cryptic,quick,quiptic`
+pub fn scrape_series() -> Vec<String> {
+    let series: Vec<String> = std::env::var("CROSSWORD_SERIES")
+        .unwrap_or(DEFAULT_SERIES.to_string())
+        .split(',')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if series.is_empty() {
+        vec![DEFAULT_SERIES.to_string()]
+    } else {
+        series
+    }
+}
+
+/// Token admins must send as `Authorization: Bearer <token>`, admin endpoints are disabled
+/// when `ADMIN_TOKEN` is not set
+pub fn admin_token() -> Option<String> {
+    std::env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// Semicolon separated schedules read from `SCRAPE_SCHEDULE`, no scheduled scraping happens when
+/// it is unset, eg. `SCRAPE_SCHEDULE=cryptic=Mon-Fri@00:05;prize=Sat@00:05`
+pub fn scrape_schedules() -> Vec<String> {
+    std::env::var("SCRAPE_SCHEDULE")
+        .unwrap_or_default()
+        .split(';')
+        .filter(|s| !s.trim().is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Timezone schedule times are in, read from `SCRAPE_SCHEDULE_TIMEZONE`, defaults to UK time
+pub fn scrape_schedule_timezone() -> Result<Tz, String> {
+    std::env::var("SCRAPE_SCHEDULE_TIMEZONE")
+        .unwrap_or("Europe/London".to_string())
+        .parse()
+}
+
+/// Maximum random delay added to each scheduled run, read from `SCRAPE_SCHEDULE_JITTER_SECONDS`
+pub fn scrape_schedule_jitter() -> Result<Duration, String> {
+    seconds_from_env("SCRAPE_SCHEDULE_JITTER_SECONDS", 300)
+}
@@ -0,0 +1,254 @@
+use chrono::Utc;
+use std::collections::HashMap;
+
+use crate::models::errors::AppError;
+use crate::models::guardian::{
+    Dimensions, GuardianCreator, GuardianCrossword, GuardianDirection, GuardianEntry, Position,
+    RebusCell, SeparatorLocations,
+};
+
+/// A crossword read from another format, before it is stored as a `GuardianCrossword`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedCrossword {
+    pub title: String,
+    pub author: String,
+    pub notes: Option<String>,
+    pub width: i64,
+    pub height: i64,
+    /// The answer for each cell row by row, `None` for black cells
+    pub cells: Vec<Option<String>>,
+    pub circled_cells: Vec<Position>,
+    pub clues: Vec<ImportedClue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedClue {
+    pub number: i64,
+    pub direction: GuardianDirection,
+    pub clue: String,
+    /// Indexes within the answer that a word ends at, eg. `[3]` for `(3,4)`
+    pub separators: Vec<i64>,
+}
+
+/// Where an entry sits in a grid numbered the usual way, left to right and top to bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberedEntry {
+    pub number: i64,
+    pub direction: GuardianDirection,
+    pub position: Position,
+    pub length: i64,
+}
+
+/// Numbers every entry of at least two cells, ordered by number with across before down
+pub fn number_grid(
+    width: i64,
+    height: i64,
+    is_white: impl Fn(i64, i64) -> bool,
+) -> Vec<NumberedEntry> {
+    let white = |x: i64, y: i64| x >= 0 && y >= 0 && x < width && y < height && is_white(x, y);
+    let run_length = |x: i64, y: i64, dx: i64, dy: i64| {
+        (0..).take_while(|i| white(x + i * dx, y + i * dy)).count() as i64
+    };
+    let mut entries = Vec::new();
+    let mut number = 0;
+    for y in 0..height {
+        for x in 0..width {
+            if !white(x, y) {
+                continue;
+            }
+            let across_length = if white(x - 1, y) {
+                0
+            } else {
+                run_length(x, y, 1, 0)
+            };
+            let down_length = if white(x, y - 1) {
+                0
+            } else {
+                run_length(x, y, 0, 1)
+            };
+            if across_length < 2 && down_length < 2 {
+                continue;
+            }
+            number += 1;
+            for (direction, length) in [
+                (GuardianDirection::Across, across_length),
+                (GuardianDirection::Down, down_length),
+            ] {
+                if length >= 2 {
+                    entries.push(NumberedEntry {
+                        number,
+                        direction,
+                        position: Position { x, y },
+                        length,
+                    });
+                }
+            }
+        }
+    }
+    entries
+}
+
+pub fn to_guardian_crossword(
+    imported: ImportedCrossword,
+    crossword_type: &str,
+) -> Result<GuardianCrossword, AppError> {
+    if imported.width < 1
+        || imported.height < 1
+        || imported.cells.len() as i64 != imported.width * imported.height
+    {
+        return Err(AppError::BadRequest(format!(
+            "Grid of {} cells does not fit dimensions {}x{}",
+            imported.cells.len(),
+            imported.width,
+            imported.height
+        )));
+    }
+    let width = imported.width;
+    let cell = |x: i64, y: i64| imported.cells[(x + y * width) as usize].as_ref();
+    let clues: HashMap<(i64, &str), &ImportedClue> = imported
+        .clues
+        .iter()
+        .map(|clue| ((clue.number, direction_name(&clue.direction)), clue))
+        .collect();
+    let entries: Result<Vec<GuardianEntry>, AppError> =
+        number_grid(imported.width, imported.height, |x, y| cell(x, y).is_some())
+            .into_iter()
+            .map(|numbered| {
+                let name = direction_name(&numbered.direction);
+                let clue = clues.get(&(numbered.number, name)).ok_or_else(|| {
+                    AppError::BadRequest(format!("No clue for {} {}", numbered.number, name))
+                })?;
+                let (dx, dy) = match numbered.direction {
+                    GuardianDirection::Across => (1, 0),
+                    GuardianDirection::Down => (0, 1),
+                };
+                let solution: String = (0..numbered.length)
+                    .filter_map(|i| {
+                        cell(numbered.position.x + i * dx, numbered.position.y + i * dy)
+                            .and_then(|answer| answer.chars().next())
+                    })
+                    .collect::<String>()
+                    .to_uppercase();
+                let id = format!("{}-{}", numbered.number, name);
+                Ok(GuardianEntry {
+                    id: id.clone(),
+                    number: numbered.number,
+                    human_number: numbered.number.to_string(),
+                    clue: clue.clue.clone(),
+                    direction: numbered.direction,
+                    length: numbered.length,
+                    group: vec![id],
+                    position: numbered.position,
+                    separator_locations: SeparatorLocations {
+                        field: clue.separators.clone(),
+                    },
+                    solution,
+                })
+            })
+            .collect();
+    let rebus_cells = imported
+        .cells
+        .iter()
+        .enumerate()
+        .filter_map(|(index, answer)| {
+            answer
+                .as_ref()
+                .filter(|answer| answer.chars().count() > 1)
+                .map(|answer| RebusCell {
+                    position: Position {
+                        x: index as i64 % width,
+                        y: index as i64 / width,
+                    },
+                    solution: answer.to_uppercase(),
+                })
+        })
+        .collect();
+    let now = Utc::now().timestamp_millis();
+    Ok(GuardianCrossword {
+        id: format!("imported/{}", crossword_type),
+        number: 0,
+        name: imported.title,
+        creator: GuardianCreator {
+            name: imported.author,
+            web_url: String::new(),
+        },
+        date: now,
+        web_publication_date: now,
+        entries: entries?,
+        solution_available: true,
+        date_solution_available: now,
+        dimensions: Dimensions {
+            cols: imported.width,
+            rows: imported.height,
+        },
+        crossword_type: crossword_type.to_string(),
+        pdf: None,
+        instructions: imported.notes,
+        circled_cells: imported.circled_cells,
+        rebus_cells,
+    })
+}
+
+pub fn direction_name(direction: &GuardianDirection) -> &'static str {
+    match direction {
+        GuardianDirection::Across => "across",
+        GuardianDirection::Down => "down",
+    }
+}
+
+/// The answer for each cell of a stored crossword row by row, `None` for black cells. White cells
+/// are empty when the solution isn't available
+pub fn solution_grid(crossword: &GuardianCrossword) -> Vec<Option<String>> {
+    let width = crossword.dimensions.cols;
+    let mut cells = vec![None; (width * crossword.dimensions.rows).max(0) as usize];
+    let mut set_cell = |position: &Position, answer: String| {
+        if let Some(cell) = cells.get_mut((position.x + position.y * width) as usize) {
+            if cell.as_ref().is_none_or(String::is_empty) {
+                *cell = Some(answer);
+            }
+        }
+    };
+    for rebus_cell in &crossword.rebus_cells {
+        set_cell(&rebus_cell.position, rebus_cell.solution.clone());
+    }
+    for entry in &crossword.entries {
+        let mut letters = entry.solution.chars();
+        for (x, y) in entry_cells(entry) {
+            let answer = letters.next().map(String::from).unwrap_or_default();
+            set_cell(&Position { x, y }, answer);
+        }
+    }
+    cells
+}
+
+/// The positions of an entry's cells in order
+pub fn entry_cells(entry: &GuardianEntry) -> Vec<(i64, i64)> {
+    let (dx, dy) = match entry.direction {
+        GuardianDirection::Across => (1, 0),
+        GuardianDirection::Down => (0, 1),
+    };
+    (0..entry.length)
+        .map(|i| (entry.position.x + i * dx, entry.position.y + i * dy))
+        .collect()
+}
+
+/// Finds where words end in an enumeration, eg. `[3]` for `3,4` or `[5]` for `5 3-2`
+pub fn enumeration_separators(enumeration: &str) -> Vec<i64> {
+    let word_lengths: Vec<i64> = enumeration
+        .split([',', ' '])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            word.split('-')
+                .filter_map(|part| part.trim().parse::<i64>().ok())
+                .sum()
+        })
+        .collect();
+    word_lengths
+        .iter()
+        .take(word_lengths.len().saturating_sub(1))
+        .scan(0, |total, length| {
+            *total += length;
+            Some(*total)
+        })
+        .collect()
+}
@@ -0,0 +1,358 @@
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Map, Value};
+
+use crate::formats::grid::{
+    direction_name, enumeration_separators, solution_grid, ImportedClue, ImportedCrossword,
+};
+use crate::models::errors::AppError;
+use crate::models::guardian::{GuardianCrossword, GuardianDirection, Position};
+
+const VERSION: &str = "http://ipuz.org/v2";
+const CROSSWORD_KIND: &str = "http://ipuz.org/crossword#1";
+const DEFAULT_BLOCK: &str = "#";
+const CIRCLE: &str = "circle";
+
+/// Parses an ipuz crossword, which must include its solution
+pub fn parse_ipuz(data: &[u8]) -> Result<ImportedCrossword, AppError> {
+    let text = String::from_utf8_lossy(data);
+    let ipuz: Value =
+        serde_json::from_str(strip_callback(text.trim())).map_err(|e| invalid(&e.to_string()))?;
+    let is_crossword = ipuz["kind"].as_array().is_some_and(|kinds| {
+        kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|kind| kind.starts_with("http://ipuz.org/crossword"))
+    });
+    if !is_crossword {
+        return Err(invalid("only crosswords are supported"));
+    }
+    let width = ipuz["dimensions"]["width"]
+        .as_i64()
+        .ok_or_else(|| invalid("missing dimensions"))?;
+    let height = ipuz["dimensions"]["height"]
+        .as_i64()
+        .ok_or_else(|| invalid("missing dimensions"))?;
+    let block = ipuz["block"].as_str().unwrap_or(DEFAULT_BLOCK);
+    let puzzle = rows(&ipuz["puzzle"], width, height, "puzzle")?;
+    let solution = rows(&ipuz["solution"], width, height, "solution")?;
+
+    let mut cells = Vec::new();
+    let mut circled_cells = Vec::new();
+    for (index, (puzzle_cell, solution_cell)) in puzzle.iter().zip(solution.iter()).enumerate() {
+        let position = Position {
+            x: index as i64 % width,
+            y: index as i64 / width,
+        };
+        if is_block(puzzle_cell, block) || is_block(solution_cell, block) {
+            cells.push(None);
+            continue;
+        }
+        let answer = cell_value(solution_cell)
+            .as_str()
+            .filter(|answer| !answer.trim().is_empty())
+            .ok_or_else(|| {
+                invalid(&format!(
+                    "missing solution at {},{}",
+                    position.x + 1,
+                    position.y + 1
+                ))
+            })?;
+        if puzzle_cell["style"]["shapebg"].as_str() == Some(CIRCLE) {
+            circled_cells.push(position);
+        }
+        cells.push(Some(answer.trim().to_uppercase()));
+    }
+
+    let mut clues = Vec::new();
+    if let Some(clue_lists) = ipuz["clues"].as_object() {
+        for (key, list) in clue_lists {
+            // keys can carry a label after the direction, eg. `Across:Horizontal`
+            let direction = match key.split(':').next().unwrap_or_default() {
+                "Across" => GuardianDirection::Across,
+                "Down" => GuardianDirection::Down,
+                _ => continue,
+            };
+            for clue in list.as_array().into_iter().flatten() {
+                clues.push(parse_clue(clue, direction.clone())?);
+            }
+        }
+    }
+    Ok(ImportedCrossword {
+        title: text_field(&ipuz, "title"),
+        author: text_field(&ipuz, "author"),
+        notes: Some(text_field(&ipuz, "notes"))
+            .filter(|notes| !notes.is_empty())
+            .or_else(|| Some(text_field(&ipuz, "intro")).filter(|intro| !intro.is_empty())),
+        width,
+        height,
+        cells,
+        circled_cells,
+        clues,
+    })
+}
+
+/// Writes a stored crossword as ipuz, leaving out the answers unless `include_solution` is set
+pub fn to_ipuz(crossword: &GuardianCrossword, include_solution: bool) -> Value {
+    let width = crossword.dimensions.cols;
+    let cells = solution_grid(crossword);
+    let mut numbers = vec![0; cells.len()];
+    for entry in &crossword.entries {
+        if let Some(number) =
+            numbers.get_mut((entry.position.x + entry.position.y * width) as usize)
+        {
+            *number = entry.number;
+        }
+    }
+    let rows = |cell: &dyn Fn(usize) -> Value| -> Vec<Vec<Value>> {
+        (0..cells.len())
+            .collect::<Vec<usize>>()
+            .chunks(width.max(1) as usize)
+            .map(|row| row.iter().map(|index| cell(*index)).collect())
+            .collect()
+    };
+    let puzzle = rows(&|index| {
+        if cells[index].is_none() {
+            return json!(DEFAULT_BLOCK);
+        }
+        let position = Position {
+            x: index as i64 % width,
+            y: index as i64 / width,
+        };
+        if crossword.circled_cells.contains(&position) {
+            json!({ "cell": numbers[index], "style": { "shapebg": CIRCLE } })
+        } else {
+            json!(numbers[index])
+        }
+    });
+
+    let mut clues = Map::new();
+    for direction in [GuardianDirection::Across, GuardianDirection::Down] {
+        let list: Vec<Value> = crossword
+            .entries
+            .iter()
+            .filter(|entry| entry.direction == direction)
+            .map(|entry| {
+                json!({
+                    "number": entry.number,
+                    "clue": entry.clue,
+                    "enumeration": enumeration(entry.length, &entry.separator_locations.field),
+                })
+            })
+            .collect();
+        clues.insert(capitalise(direction_name(&direction)), Value::Array(list));
+    }
+
+    let mut ipuz = json!({
+        "version": VERSION,
+        "kind": [CROSSWORD_KIND],
+        "title": crossword.name,
+        "author": crossword.creator.name,
+        "date": Utc
+            .timestamp_millis_opt(crossword.date)
+            .single()
+            .map(|date| date.format("%m/%d/%Y").to_string()),
+        "dimensions": { "width": width, "height": crossword.dimensions.rows },
+        "block": DEFAULT_BLOCK,
+        "empty": 0,
+        "puzzle": puzzle,
+        "clues": clues,
+    });
+    if let Some(instructions) = &crossword.instructions {
+        ipuz["notes"] = json!(instructions);
+    }
+    let has_solution = cells.iter().flatten().all(|answer| !answer.is_empty());
+    if include_solution && has_solution {
+        let solution = rows(&|index| {
+            cells[index]
+                .as_ref()
+                .map_or(json!(DEFAULT_BLOCK), |answer| json!(answer))
+        });
+        ipuz["solution"] = json!(solution);
+    }
+    ipuz
+}
+
+/// ipuz files are sometimes served wrapped as `ipuz({...})`
+fn strip_callback(text: &str) -> &str {
+    text.strip_prefix("ipuz(")
+        .and_then(|inner| inner.trim_end().strip_suffix(')'))
+        .unwrap_or(text)
+}
+
+/// Flattens a grid of rows, checking it fits the dimensions
+fn rows(grid: &Value, width: i64, height: i64, name: &str) -> Result<Vec<Value>, AppError> {
+    let rows = grid
+        .as_array()
+        .ok_or_else(|| invalid(&format!("missing {}", name)))?;
+    let fits = rows.len() as i64 == height
+        && rows
+            .iter()
+            .all(|row| row.as_array().is_some_and(|row| row.len() as i64 == width));
+    if !fits {
+        return Err(invalid(&format!(
+            "{} does not fit dimensions {}x{}",
+            name, width, height
+        )));
+    }
+    Ok(rows
+        .iter()
+        .flat_map(|row| row.as_array().cloned().unwrap_or_default())
+        .collect())
+}
+
+/// Cells are either a plain value or an object holding it with some styling
+fn cell_value(cell: &Value) -> &Value {
+    match cell {
+        Value::Object(fields) => fields
+            .get("cell")
+            .or_else(|| fields.get("value"))
+            .unwrap_or(&Value::Null),
+        _ => cell,
+    }
+}
+
+/// Omitted cells are `null` and are treated as black
+fn is_block(cell: &Value, block: &str) -> bool {
+    match cell_value(cell) {
+        Value::Null => true,
+        Value::String(value) => value == block,
+        _ => false,
+    }
+}
+
+/// Clues are `[number, clue]` pairs or objects with a `number`, `clue` and `enumeration`
+fn parse_clue(clue: &Value, direction: GuardianDirection) -> Result<ImportedClue, AppError> {
+    let (number, text, enumeration) = match clue {
+        Value::Array(pair) if pair.len() == 2 => (&pair[0], &pair[1], &Value::Null),
+        Value::Object(fields) => (
+            fields.get("number").unwrap_or(&Value::Null),
+            fields.get("clue").unwrap_or(&Value::Null),
+            fields.get("enumeration").unwrap_or(&Value::Null),
+        ),
+        _ => return Err(invalid(&format!("unreadable clue {}", clue))),
+    };
+    let number = number
+        .as_i64()
+        .or_else(|| {
+            number
+                .as_str()
+                .and_then(|number| number.trim().parse().ok())
+        })
+        .ok_or_else(|| invalid(&format!("unreadable clue number {}", number)))?;
+    Ok(ImportedClue {
+        number,
+        direction,
+        clue: text.as_str().unwrap_or_default().to_string(),
+        separators: enumeration
+            .as_str()
+            .map(enumeration_separators)
+            .unwrap_or_default(),
+    })
+}
+
+/// Writes an enumeration from where words end, eg. `3,4` for a length of 7 ending a word at 3
+fn enumeration(length: i64, separators: &[i64]) -> String {
+    let mut word_lengths = Vec::new();
+    let mut start = 0;
+    for end in separators.iter().chain([length].iter()) {
+        word_lengths.push((end - start).to_string());
+        start = *end;
+    }
+    word_lengths.join(",")
+}
+
+fn text_field(ipuz: &Value, field: &str) -> String {
+    ipuz[field].as_str().unwrap_or_default().to_string()
+}
+
+fn capitalise(value: &str) -> String {
+    let mut chars = value.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::BadRequest(format!("Invalid ipuz file: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::grid::to_guardian_crossword;
+
+    fn clue(number: i64, direction: GuardianDirection, clue: &str) -> ImportedClue {
+        ImportedClue {
+            number,
+            direction,
+            clue: clue.to_string(),
+            separators: Vec::new(),
+        }
+    }
+
+    /// A 3x3 grid with a rebus square in the middle of the left column, the bottom right square
+    /// circled and a two word answer along the bottom
+    fn crossword() -> GuardianCrossword {
+        let cells = ["C", "A", "T", "HAT", "", "O", "E", "A", "R"]
+            .into_iter()
+            .map(|answer| Some(answer.to_string()).filter(|answer| !answer.is_empty()))
+            .collect();
+        let imported = ImportedCrossword {
+            title: "Test puzzle".to_string(),
+            author: "Tester".to_string(),
+            notes: Some("Some notes".to_string()),
+            width: 3,
+            height: 3,
+            cells,
+            circled_cells: vec![Position { x: 2, y: 2 }],
+            clues: vec![
+                clue(1, GuardianDirection::Across, "Feline"),
+                clue(1, GuardianDirection::Down, "Revolutionary taking headgear"),
+                clue(2, GuardianDirection::Down, "Rocky hill"),
+                ImportedClue {
+                    separators: vec![1],
+                    ..clue(3, GuardianDirection::Across, "Listener")
+                },
+            ],
+        };
+        to_guardian_crossword(imported, "mine").unwrap()
+    }
+
+    fn assert_invalid(data: &[u8], message: &str) {
+        match parse_ipuz(data) {
+            Err(AppError::BadRequest(error)) => assert!(
+                error.contains(message),
+                "expected {:?} in {:?}",
+                message,
+                error
+            ),
+            other => panic!("expected {:?}, got {:?}", message, other),
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let crossword = crossword();
+        let data = to_ipuz(&crossword, true).to_string();
+        let read_back =
+            to_guardian_crossword(parse_ipuz(data.as_bytes()).unwrap(), "mine").unwrap();
+        assert_eq!(read_back.entries, crossword.entries);
+        assert_eq!(solution_grid(&read_back), solution_grid(&crossword));
+        assert_eq!(read_back.circled_cells, crossword.circled_cells);
+        assert_eq!(read_back.instructions, crossword.instructions);
+    }
+
+    #[test]
+    fn reads_callback_wrapped_files() {
+        let data = format!("ipuz({})", to_ipuz(&crossword(), true));
+        assert!(parse_ipuz(data.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn leaves_out_the_solution_unless_asked() {
+        let ipuz = to_ipuz(&crossword(), false);
+        assert!(ipuz.get("solution").is_none());
+        assert_invalid(ipuz.to_string().as_bytes(), "missing solution");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod grid;
+pub mod ipuz;
+pub mod puz;
+pub mod xd;
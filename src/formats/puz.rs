@@ -0,0 +1,626 @@
+use std::collections::HashMap;
+
+use crate::formats::grid::{number_grid, solution_grid, ImportedClue, ImportedCrossword};
+use crate::models::errors::AppError;
+use crate::models::guardian::{GuardianCrossword, GuardianDirection, Position};
+
+const MAGIC: &[u8] = b"ACROSS&DOWN\0";
+const HEADER_LENGTH: usize = 0x34;
+const MASK: &[u8] = b"ICHEATED";
+const VERSION: &[u8] = b"1.3\0";
+const BLACK: u8 = b'.';
+const EMPTY: u8 = b'-';
+const UNKNOWN: u8 = b'X';
+const CIRCLED: u8 = 0x80;
+const NORMAL_PUZZLE: u16 = 0x0001;
+const NO_SOLUTION: u16 = 0x0002;
+const SCRAMBLED: u16 = 0x0004;
+
+/// Parses an Across Lite `.puz` file, verifying its checksums. Files marked as having no solution
+/// are read with every white cell's answer empty
+pub fn parse_puz(data: &[u8]) -> Result<ImportedCrossword, AppError> {
+    // some files have a preamble before the header, the magic string locates it
+    let start = data
+        .windows(MAGIC.len())
+        .position(|window| window == MAGIC)
+        .and_then(|index| index.checked_sub(2))
+        .ok_or_else(|| invalid("missing ACROSS&DOWN header"))?;
+    let mut reader = PuzReader {
+        data: &data[start..],
+        offset: 0,
+    };
+    let header = reader.take(HEADER_LENGTH)?;
+    let width = header[0x2C] as usize;
+    let height = header[0x2D] as usize;
+    let clue_count = read_u16(header, 0x2E) as usize;
+    let solution_state = read_u16(header, 0x32);
+    if solution_state & SCRAMBLED != 0 {
+        return Err(invalid("scrambled puzzles are not supported"));
+    }
+    let has_solution = solution_state & NO_SOLUTION == 0;
+    let utf8 = header[0x18] >= b'2';
+    let solution = reader.take(width * height)?;
+    let fill = reader.take(width * height)?;
+    let title = reader.string(utf8)?;
+    let author = reader.string(utf8)?;
+    let copyright = reader.string(utf8)?;
+    let clues: Result<Vec<String>, AppError> =
+        (0..clue_count).map(|_| reader.string(utf8)).collect();
+    let clues = clues?;
+    let notes = reader.string(utf8).unwrap_or_default();
+    let strings = PuzStrings {
+        title: &title,
+        author: &author,
+        copyright: &copyright,
+        clues: &clues,
+        notes: &notes,
+        utf8,
+        checksum_notes: header[0x18..0x1B] >= b"1.3"[..],
+    };
+    verify_checksums(header, solution, fill, &strings)?;
+    let extensions = reader.extensions()?;
+
+    let rebus = match (extensions.get(b"GRBS"), extensions.get(b"RTBL")) {
+        (Some(grid), Some(table)) => Some((*grid, parse_rebus_table(table)?)),
+        _ => None,
+    };
+    let cells: Vec<Option<String>> = solution
+        .iter()
+        .enumerate()
+        .map(|(index, square)| {
+            if *square == BLACK {
+                return None;
+            }
+            if !has_solution {
+                return Some(String::new());
+            }
+            let rebus_answer = rebus.as_ref().and_then(|(grid, table)| {
+                grid.get(index)
+                    .filter(|key| **key > 0)
+                    .and_then(|key| table.get(&(*key as i64 - 1)))
+                    .cloned()
+            });
+            Some(rebus_answer.unwrap_or_else(|| (*square as char).to_string()))
+        })
+        .collect();
+    let circled_cells = extensions
+        .get(b"GEXT")
+        .map(|markup| {
+            markup
+                .iter()
+                .enumerate()
+                .filter(|(_, flags)| **flags & CIRCLED != 0)
+                .map(|(index, _)| Position {
+                    x: (index % width) as i64,
+                    y: (index / width) as i64,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // clues are listed by number, with across before down for the same number
+    let numbered = number_grid(width as i64, height as i64, |x, y| {
+        solution[x as usize + y as usize * width] != BLACK
+    });
+    if numbered.len() != clues.len() {
+        return Err(invalid(&format!(
+            "grid has {} entries but {} clues",
+            numbered.len(),
+            clues.len()
+        )));
+    }
+    let clues = numbered
+        .into_iter()
+        .zip(clues)
+        .map(|(entry, clue)| ImportedClue {
+            number: entry.number,
+            direction: entry.direction,
+            clue,
+            separators: Vec::new(),
+        })
+        .collect();
+    Ok(ImportedCrossword {
+        title,
+        author,
+        notes: Some(notes).filter(|notes| !notes.is_empty()),
+        width: width as i64,
+        height: height as i64,
+        cells,
+        circled_cells,
+        clues,
+    })
+}
+
+/// Writes a stored crossword as an Across Lite `.puz` file, with `entered` holding any letters
+/// already filled in keyed by their position. Cells without a known solution are written as `X`,
+/// and a crossword with no known answers is marked as having no solution
+pub fn write_puz(
+    crossword: &GuardianCrossword,
+    entered: &HashMap<(i64, i64), String>,
+) -> Result<Vec<u8>, AppError> {
+    let width = crossword.dimensions.cols;
+    let height = crossword.dimensions.rows;
+    let (Ok(width_byte), Ok(height_byte)) = (u8::try_from(width), u8::try_from(height)) else {
+        return Err(AppError::BadRequest(format!(
+            "A {}x{} grid is too large for a .puz file",
+            width, height
+        )));
+    };
+    let cells = solution_grid(crossword);
+    let first_letter = |answer: &str, default: u8| {
+        answer
+            .chars()
+            .next()
+            .filter(char::is_ascii_alphanumeric)
+            .map_or(default, |letter| letter.to_ascii_uppercase() as u8)
+    };
+    let solution: Vec<u8> = cells
+        .iter()
+        .map(|cell| {
+            cell.as_ref()
+                .map_or(BLACK, |answer| first_letter(answer, UNKNOWN))
+        })
+        .collect();
+    let solution_state = if cells.iter().flatten().all(String::is_empty) {
+        NO_SOLUTION
+    } else {
+        0
+    };
+    let fill: Vec<u8> = cells
+        .iter()
+        .enumerate()
+        .map(|(index, cell)| {
+            let position = (index as i64 % width, index as i64 / width);
+            match cell {
+                None => BLACK,
+                Some(_) => entered
+                    .get(&position)
+                    .map_or(EMPTY, |value| first_letter(value, EMPTY)),
+            }
+        })
+        .collect();
+
+    // clues are listed by number, with across before down for the same number
+    let mut entries: Vec<_> = crossword.entries.iter().collect();
+    entries.sort_by_key(|entry| {
+        (
+            entry.number,
+            matches!(entry.direction, GuardianDirection::Down),
+        )
+    });
+    let clues: Vec<String> = entries.iter().map(|entry| entry.clue.clone()).collect();
+    let notes = crossword.instructions.clone().unwrap_or_default();
+    let strings = PuzStrings {
+        title: &crossword.name,
+        author: &crossword.creator.name,
+        copyright: "",
+        clues: &clues,
+        notes: &notes,
+        utf8: false,
+        checksum_notes: true,
+    };
+    let clue_count = u16::try_from(clues.len())
+        .map_err(|_| AppError::BadRequest("Too many clues for a .puz file".to_string()))?;
+    let mut cib_bytes = vec![width_byte, height_byte];
+    cib_bytes.extend_from_slice(&clue_count.to_le_bytes());
+    cib_bytes.extend_from_slice(&NORMAL_PUZZLE.to_le_bytes());
+    cib_bytes.extend_from_slice(&solution_state.to_le_bytes());
+    let expected = checksums(&cib_bytes, &solution, &fill, &strings);
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&expected.global.to_le_bytes());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&expected.cib.to_le_bytes());
+    data.extend_from_slice(&expected.masked);
+    data.extend_from_slice(VERSION);
+    data.extend_from_slice(&[0; 0x2C - 0x1C]);
+    data.extend_from_slice(&cib_bytes);
+    data.extend_from_slice(&solution);
+    data.extend_from_slice(&fill);
+    for value in [strings.title, strings.author, strings.copyright]
+        .into_iter()
+        .chain(clues.iter().map(String::as_str))
+        .chain([strings.notes])
+    {
+        data.extend_from_slice(&encode(value, strings.utf8));
+        data.push(0);
+    }
+
+    let rebus_answers: Vec<&String> = cells
+        .iter()
+        .flatten()
+        .filter(|answer| answer.chars().count() > 1)
+        .fold(Vec::new(), |mut answers, answer| {
+            if !answers.contains(&answer) {
+                answers.push(answer);
+            }
+            answers
+        });
+    if !rebus_answers.is_empty() {
+        let grid: Vec<u8> = cells
+            .iter()
+            .map(|cell| {
+                cell.as_ref()
+                    .and_then(|answer| rebus_answers.iter().position(|rebus| *rebus == answer))
+                    .map_or(0, |key| key as u8 + 1)
+            })
+            .collect();
+        let table: String = rebus_answers
+            .iter()
+            .enumerate()
+            .map(|(key, answer)| format!("{:>2}:{};", key, answer))
+            .collect();
+        write_extension(&mut data, b"GRBS", &grid);
+        write_extension(&mut data, b"RTBL", &encode(&table, false));
+    }
+    if !crossword.circled_cells.is_empty() {
+        let mut markup = vec![0; cells.len()];
+        for position in &crossword.circled_cells {
+            if let Some(flags) = markup.get_mut((position.x + position.y * width) as usize) {
+                *flags |= CIRCLED;
+            }
+        }
+        write_extension(&mut data, b"GEXT", &markup);
+    }
+    Ok(data)
+}
+
+fn write_extension(data: &mut Vec<u8>, title: &[u8; 4], section: &[u8]) {
+    data.extend_from_slice(title);
+    data.extend_from_slice(&(section.len() as u16).to_le_bytes());
+    data.extend_from_slice(&checksum_region(section, 0).to_le_bytes());
+    data.extend_from_slice(section);
+    data.push(0);
+}
+
+struct PuzStrings<'a> {
+    title: &'a str,
+    author: &'a str,
+    copyright: &'a str,
+    clues: &'a [String],
+    notes: &'a str,
+    utf8: bool,
+    /// Notes only count towards the checksums from version 1.3
+    checksum_notes: bool,
+}
+
+struct PuzReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PuzReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], AppError> {
+        let end = self.offset + length;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or_else(|| invalid("file ends early"))?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn string(&mut self, utf8: bool) -> Result<String, AppError> {
+        let length = self.data[self.offset.min(self.data.len())..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        let bytes = self.take(length)?;
+        self.take(1)?;
+        Ok(decode(bytes, utf8))
+    }
+
+    /// Reads the extra sections after the strings, keyed by their four letter title
+    fn extensions(&mut self) -> Result<HashMap<&'a [u8; 4], &'a [u8]>, AppError> {
+        let mut extensions = HashMap::new();
+        while self.data.len() - self.offset >= 8 {
+            let title: &[u8; 4] = self
+                .take(4)?
+                .try_into()
+                .map_err(|_| invalid("bad section"))?;
+            let length_bytes = self.take(2)?;
+            let length = read_u16(length_bytes, 0) as usize;
+            let checksum = read_u16(self.take(2)?, 0);
+            let section = self.take(length)?;
+            self.take(1)?;
+            if checksum_region(section, 0) != checksum {
+                return Err(invalid(&format!(
+                    "bad checksum for {} section",
+                    String::from_utf8_lossy(title)
+                )));
+            }
+            extensions.insert(title, section);
+        }
+        Ok(extensions)
+    }
+}
+
+fn verify_checksums(
+    header: &[u8],
+    solution: &[u8],
+    fill: &[u8],
+    strings: &PuzStrings,
+) -> Result<(), AppError> {
+    let expected = checksums(&header[0x2C..0x34], solution, fill, strings);
+    if read_u16(header, 0x0E) != expected.cib {
+        return Err(invalid("bad header checksum"));
+    }
+    if read_u16(header, 0x00) != expected.global {
+        return Err(invalid("bad file checksum"));
+    }
+    if header[0x10..0x18] != expected.masked {
+        return Err(invalid("bad masked checksums"));
+    }
+    Ok(())
+}
+
+struct PuzChecksums {
+    cib: u16,
+    global: u16,
+    masked: [u8; 8],
+}
+
+/// Calculates the checksums stored in a `.puz` header from the 8 bytes at 0x2C onwards and the
+/// rest of the puzzle
+fn checksums(cib_bytes: &[u8], solution: &[u8], fill: &[u8], strings: &PuzStrings) -> PuzChecksums {
+    let cib = checksum_region(cib_bytes, 0);
+    let solution_checksum = checksum_region(solution, 0);
+    let fill_checksum = checksum_region(fill, 0);
+    let text_checksum = checksum_strings(strings, 0);
+    let global = checksum_strings(
+        strings,
+        checksum_region(fill, checksum_region(solution, cib)),
+    );
+    let parts = [cib, solution_checksum, fill_checksum, text_checksum];
+    let mut masked = [0; 8];
+    for (index, part) in parts.iter().enumerate() {
+        masked[index] = MASK[index] ^ (part & 0xFF) as u8;
+        masked[index + 4] = MASK[index + 4] ^ (part >> 8) as u8;
+    }
+    PuzChecksums {
+        cib,
+        global,
+        masked,
+    }
+}
+
+fn checksum_strings(strings: &PuzStrings, checksum: u16) -> u16 {
+    let mut checksum = checksum;
+    for value in [strings.title, strings.author, strings.copyright] {
+        if !value.is_empty() {
+            checksum = checksum_region(&encode(value, strings.utf8), checksum);
+            checksum = checksum_region(&[0], checksum);
+        }
+    }
+    for clue in strings.clues {
+        checksum = checksum_region(&encode(clue, strings.utf8), checksum);
+    }
+    if strings.checksum_notes && !strings.notes.is_empty() {
+        checksum = checksum_region(&encode(strings.notes, strings.utf8), checksum);
+        checksum = checksum_region(&[0], checksum);
+    }
+    checksum
+}
+
+fn checksum_region(data: &[u8], checksum: u16) -> u16 {
+    data.iter().fold(checksum, |checksum, byte| {
+        checksum.rotate_right(1).wrapping_add(*byte as u16)
+    })
+}
+
+/// Parses the rebus table, eg. ` 0:HEART; 1:STAR;`
+fn parse_rebus_table(table: &[u8]) -> Result<HashMap<i64, String>, AppError> {
+    String::from_utf8_lossy(table)
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (key, answer) = entry
+                .split_once(':')
+                .ok_or_else(|| invalid("bad rebus table"))?;
+            let key = key
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| invalid("bad rebus table"))?;
+            Ok((key, answer.to_string()))
+        })
+        .collect()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Strings are ISO-8859-1 before version 2.0 and UTF-8 after
+fn decode(bytes: &[u8], utf8: bool) -> String {
+    if utf8 {
+        String::from_utf8_lossy(bytes).to_string()
+    } else {
+        bytes.iter().map(|byte| *byte as char).collect()
+    }
+}
+
+fn encode(value: &str, utf8: bool) -> Vec<u8> {
+    if utf8 {
+        value.as_bytes().to_vec()
+    } else {
+        // typographic punctuation is common in clues but outside ISO-8859-1
+        value
+            .chars()
+            .flat_map(|c| match c {
+                '\u{2018}' | '\u{2019}' => vec![b'\''],
+                '\u{201C}' | '\u{201D}' => vec![b'"'],
+                '\u{2013}' | '\u{2014}' => vec![b'-'],
+                '\u{2026}' => b"...".to_vec(),
+                c if (c as u32) < 256 => vec![c as u8],
+                _ => vec![b'?'],
+            })
+            .collect()
+    }
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::BadRequest(format!("Invalid .puz file: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::grid::to_guardian_crossword;
+    use crate::services::crossword_service::without_solution;
+
+    fn clue(number: i64, direction: GuardianDirection, clue: &str) -> ImportedClue {
+        ImportedClue {
+            number,
+            direction,
+            clue: clue.to_string(),
+            separators: Vec::new(),
+        }
+    }
+
+    /// A 3x3 grid with a rebus square in the middle of the left column and the bottom right
+    /// square circled
+    fn imported() -> ImportedCrossword {
+        let cells = ["C", "A", "T", "HAT", "", "O", "E", "A", "R"]
+            .into_iter()
+            .map(|answer| Some(answer.to_string()).filter(|answer| !answer.is_empty()))
+            .collect();
+        ImportedCrossword {
+            title: "Test puzzle".to_string(),
+            author: "Tester".to_string(),
+            notes: Some("Some notes".to_string()),
+            width: 3,
+            height: 3,
+            cells,
+            circled_cells: vec![Position { x: 2, y: 2 }],
+            clues: vec![
+                clue(1, GuardianDirection::Across, "Feline"),
+                clue(1, GuardianDirection::Down, "Revolutionary taking headgear"),
+                clue(2, GuardianDirection::Down, "Rocky hill"),
+                clue(3, GuardianDirection::Across, "Listener"),
+            ],
+        }
+    }
+
+    fn puz() -> Vec<u8> {
+        let crossword = to_guardian_crossword(imported(), "mine").unwrap();
+        write_puz(&crossword, &HashMap::new()).unwrap()
+    }
+
+    fn assert_invalid(data: &[u8], message: &str) {
+        match parse_puz(data) {
+            Err(AppError::BadRequest(error)) => assert!(
+                error.contains(message),
+                "expected {:?} in {:?}",
+                message,
+                error
+            ),
+            other => panic!("expected {:?}, got {:?}", message, other),
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        assert_eq!(parse_puz(&puz()).unwrap(), imported());
+    }
+
+    #[test]
+    fn finds_the_header_after_a_preamble() {
+        let mut data = b"preamble".to_vec();
+        data.extend(puz());
+        assert_eq!(parse_puz(&data).unwrap(), imported());
+    }
+
+    #[test]
+    fn writes_the_fill() {
+        let crossword = to_guardian_crossword(imported(), "mine").unwrap();
+        let entered = HashMap::from([((1, 0), "a".to_string()), ((0, 1), "HAT".to_string())]);
+        let data = write_puz(&crossword, &entered).unwrap();
+        let fill = &data[HEADER_LENGTH + 9..HEADER_LENGTH + 18];
+        assert_eq!(fill, b"-A-H.----");
+        assert_eq!(parse_puz(&data).unwrap(), imported());
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut data = puz();
+        // change a letter of the solution
+        data[HEADER_LENGTH] = b'D';
+        assert_invalid(&data, "bad file checksum");
+
+        let mut data = puz();
+        data[0x10] ^= 0xFF;
+        assert_invalid(&data, "bad masked checksums");
+
+        let mut data = puz();
+        data[0x2C] = 4;
+        assert_invalid(&data, "bad header checksum");
+
+        let mut data = puz();
+        let rebus_grid = data
+            .windows(4)
+            .position(|window| window == b"GRBS")
+            .unwrap();
+        data[rebus_grid + 8] ^= 0xFF;
+        assert_invalid(&data, "bad checksum for GRBS section");
+    }
+
+    #[test]
+    fn marks_withheld_answers_as_no_solution() {
+        assert_eq!(read_u16(&puz(), 0x32), 0);
+
+        let crossword = without_solution(to_guardian_crossword(imported(), "mine").unwrap());
+        let data = write_puz(&crossword, &HashMap::new()).unwrap();
+        assert_eq!(read_u16(&data, 0x32), NO_SOLUTION);
+        assert_eq!(&data[HEADER_LENGTH..HEADER_LENGTH + 9], b"XXXX.XXXX");
+        let parsed = parse_puz(&data).unwrap();
+        assert_eq!(
+            parsed.cells,
+            imported()
+                .cells
+                .iter()
+                .map(|cell| cell.as_ref().map(|_| String::new()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(parsed.clues, imported().clues);
+    }
+
+    #[test]
+    fn rejects_scrambled_puzzles() {
+        let mut data = puz();
+        data[0x32] |= SCRAMBLED as u8;
+        assert_invalid(&data, "scrambled");
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = puz();
+        assert_invalid(&data[..10], "missing ACROSS&DOWN header");
+        let strings_end = data
+            .windows(b"Some notes\0".len())
+            .position(|window| window == b"Some notes\0")
+            .unwrap();
+        for length in 0x0E..strings_end {
+            assert!(
+                parse_puz(&data[..length]).is_err(),
+                "a file cut to {} bytes should be rejected",
+                length
+            );
+        }
+        // a cut in the extensions must not panic either
+        for length in strings_end..data.len() {
+            let _ = parse_puz(&data[..length]);
+        }
+    }
+
+    #[test]
+    fn rejects_clue_counts_not_matching_the_grid() {
+        let mut crossword = to_guardian_crossword(imported(), "mine").unwrap();
+        let mut extra_entry = crossword.entries[0].clone();
+        extra_entry.number = 4;
+        crossword.entries.push(extra_entry);
+        assert_invalid(
+            &write_puz(&crossword, &HashMap::new()).unwrap(),
+            "grid has 4 entries but 5 clues",
+        );
+    }
+}
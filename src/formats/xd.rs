@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+
+use chrono::{TimeZone, Utc};
+
+use crate::formats::grid::{
+    entry_cells, enumeration_separators, solution_grid, ImportedClue, ImportedCrossword,
+};
+use crate::models::errors::AppError;
+use crate::models::guardian::{GuardianCrossword, GuardianDirection, Position};
+
+const BLOCK: char = '#';
+const NOT_A_CELL: char = '_';
+const ANSWER_SEPARATOR: &str = " ~ ";
+const REBUS_KEYS: &str = "1234567890";
+
+/// Parses a crossword in the plain text `.xd` format, made up of metadata, grid, clue and notes
+/// sections separated by blank lines
+pub fn parse_xd(data: &[u8]) -> Result<ImportedCrossword, AppError> {
+    let text = String::from_utf8_lossy(data);
+    let mut sections = sections(&text).into_iter().peekable();
+    let metadata: HashMap<String, String> = match sections.peek() {
+        Some(section) if section.iter().all(|line| line.contains(':')) => sections
+            .next()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_lowercase(), value.trim().to_string()))
+            .collect(),
+        _ => HashMap::new(),
+    };
+    let rebus = metadata
+        .get("rebus")
+        .map(|rebus| parse_rebus(rebus))
+        .transpose()?
+        .unwrap_or_default();
+
+    let grid = sections.next().ok_or_else(|| invalid("missing grid"))?;
+    let width = grid[0].chars().count();
+    if grid.iter().any(|line| line.chars().count() != width) {
+        return Err(invalid("grid rows are different lengths"));
+    }
+    let mut cells = Vec::new();
+    let mut circled_cells = Vec::new();
+    for (y, line) in grid.iter().enumerate() {
+        for (x, square) in line.chars().enumerate() {
+            if square == BLOCK || square == NOT_A_CELL {
+                cells.push(None);
+                continue;
+            }
+            // lowercase letters mark special squares, which are shown circled
+            if square.is_lowercase() {
+                circled_cells.push(Position {
+                    x: x as i64,
+                    y: y as i64,
+                });
+            }
+            let answer = rebus
+                .get(&square)
+                .cloned()
+                .unwrap_or_else(|| square.to_uppercase().to_string());
+            cells.push(Some(answer));
+        }
+    }
+
+    let mut clues = Vec::new();
+    let mut notes = Vec::new();
+    for section in sections {
+        for line in section {
+            match parse_clue(line) {
+                Some(clue) if notes.is_empty() => clues.push(clue),
+                _ => notes.push(line),
+            }
+        }
+    }
+    let notes = metadata
+        .get("notes")
+        .cloned()
+        .into_iter()
+        .chain(Some(notes.join("\n")))
+        .find(|notes| !notes.is_empty());
+    Ok(ImportedCrossword {
+        title: metadata.get("title").cloned().unwrap_or_default(),
+        author: metadata.get("author").cloned().unwrap_or_default(),
+        notes,
+        width: width as i64,
+        height: grid.len() as i64,
+        cells,
+        circled_cells,
+        clues,
+    })
+}
+
+/// Writes a stored crossword as `.xd`, whose grid is made of the answers so it needs the solution.
+/// Answers longer than a letter are written as rebus squares, and circled squares in lowercase
+pub fn to_xd(crossword: &GuardianCrossword) -> Result<String, AppError> {
+    let width = crossword.dimensions.cols;
+    let cells = solution_grid(crossword);
+    if cells.iter().flatten().any(String::is_empty) {
+        return Err(AppError::BadRequest(
+            "An .xd file needs the crossword's solution".to_string(),
+        ));
+    }
+    let mut rebus_answers: Vec<&String> = Vec::new();
+    for answer in cells.iter().flatten() {
+        if answer.chars().count() > 1 && !rebus_answers.contains(&answer) {
+            rebus_answers.push(answer);
+        }
+    }
+    if rebus_answers.len() > REBUS_KEYS.len() {
+        return Err(AppError::BadRequest(format!(
+            "An .xd file can't hold more than {} rebus answers",
+            REBUS_KEYS.len()
+        )));
+    }
+
+    let mut metadata = vec![
+        format!("Title: {}", crossword.name),
+        format!("Author: {}", crossword.creator.name),
+    ];
+    if let Some(date) = Utc.timestamp_millis_opt(crossword.date).single() {
+        metadata.push(format!("Date: {}", date.format("%Y-%m-%d")));
+    }
+    if !rebus_answers.is_empty() {
+        let rebus: Vec<String> = REBUS_KEYS
+            .chars()
+            .zip(&rebus_answers)
+            .map(|(key, answer)| format!("{}={}", key, answer))
+            .collect();
+        metadata.push(format!("Rebus: {}", rebus.join(" ")));
+    }
+    if let Some(instructions) = crossword
+        .instructions
+        .as_ref()
+        .filter(|notes| !notes.is_empty())
+    {
+        metadata.push(format!("Notes: {}", instructions.replace('\n', " ")));
+    }
+
+    let grid: Vec<String> = cells
+        .chunks(width.max(1) as usize)
+        .enumerate()
+        .map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(x, cell)| {
+                    let Some(answer) = cell else {
+                        return BLOCK.to_string();
+                    };
+                    if let Some(key) = rebus_answers.iter().position(|rebus| *rebus == answer) {
+                        return REBUS_KEYS[key..key + 1].to_string();
+                    }
+                    let position = Position {
+                        x: x as i64,
+                        y: y as i64,
+                    };
+                    if crossword.circled_cells.contains(&position) {
+                        answer.to_lowercase()
+                    } else {
+                        answer.to_uppercase()
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut sections = vec![metadata.join("\n"), grid.join("\n")];
+    for direction in [GuardianDirection::Across, GuardianDirection::Down] {
+        let mut entries: Vec<_> = crossword
+            .entries
+            .iter()
+            .filter(|entry| entry.direction == direction)
+            .collect();
+        entries.sort_by_key(|entry| entry.number);
+        let prefix = match direction {
+            GuardianDirection::Across => 'A',
+            GuardianDirection::Down => 'D',
+        };
+        let clues: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                let answer: String = entry_cells(entry)
+                    .into_iter()
+                    .filter_map(|(x, y)| cells.get((x + y * width) as usize).cloned().flatten())
+                    .collect();
+                format!(
+                    "{}{}. {}{}{}",
+                    prefix, entry.number, entry.clue, ANSWER_SEPARATOR, answer
+                )
+            })
+            .collect();
+        if !clues.is_empty() {
+            sections.push(clues.join("\n"));
+        }
+    }
+    Ok(sections.join("\n\n") + "\n")
+}
+
+/// Groups the non-blank lines, skipping any `## Section` headings
+fn sections(text: &str) -> Vec<Vec<&str>> {
+    let mut sections = vec![Vec::new()];
+    for line in text.lines().map(str::trim_end) {
+        if line.starts_with("## ") {
+            continue;
+        }
+        match sections.last_mut() {
+            Some(section) if !line.trim().is_empty() => section.push(line.trim_start()),
+            Some(section) if !section.is_empty() => sections.push(Vec::new()),
+            _ => {}
+        }
+    }
+    sections.retain(|section| !section.is_empty());
+    sections
+}
+
+/// Parses the rebus metadata, eg. `1=HEART 2=STAR`
+fn parse_rebus(rebus: &str) -> Result<HashMap<char, String>, AppError> {
+    rebus
+        .split_whitespace()
+        .map(|pair| {
+            let (key, answer) = pair.split_once('=').ok_or_else(|| invalid("bad rebus"))?;
+            let mut key_chars = key.chars();
+            match (key_chars.next(), key_chars.next()) {
+                (Some(key), None) => Ok((key, answer.to_uppercase())),
+                _ => Err(invalid("bad rebus")),
+            }
+        })
+        .collect()
+}
+
+/// Parses a clue line, eg. `A1. Feline (3) ~ CAT`, giving `None` for lines that aren't clues
+fn parse_clue(line: &str) -> Option<ImportedClue> {
+    let direction = match line.chars().next() {
+        Some('A') => GuardianDirection::Across,
+        Some('D') => GuardianDirection::Down,
+        _ => return None,
+    };
+    let (number, rest) = line[1..].split_once(". ")?;
+    let number = number.parse::<i64>().ok()?;
+    let clue = rest
+        .rsplit_once(ANSWER_SEPARATOR)
+        .map_or(rest, |(clue, _)| clue)
+        .trim();
+    // cryptic clues end with their enumeration, eg. `(3,4)`
+    let separators = clue
+        .strip_suffix(')')
+        .and_then(|clue| clue.rsplit_once('('))
+        .map(|(_, enumeration)| enumeration_separators(enumeration))
+        .unwrap_or_default();
+    Some(ImportedClue {
+        number,
+        direction,
+        clue: clue.to_string(),
+        separators,
+    })
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::BadRequest(format!("Invalid .xd file: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::grid::{direction_name, to_guardian_crossword};
+
+    const XD: &str = "Title: Test Puzzle
+Author: A. Setter
+Rebus: 1=hat
+Notes: Circled squares are special
+
+CAT
+1#O
+EAr
+
+A1. Feline (3) ~ CAT
+A3. Hearing organ (3) ~ EAR
+D1. Revolutionary taking headgear (1,4) ~ CHATE
+D2. Rocky hill (3) ~ TOR
+";
+
+    /// The answer given after ` ~ ` on each clue line, keyed by number and direction
+    fn clue_answers(xd: &str) -> HashMap<(i64, &'static str), String> {
+        xd.lines()
+            .filter_map(|line| {
+                let clue = parse_clue(line)?;
+                let (_, answer) = line.rsplit_once(ANSWER_SEPARATOR)?;
+                Some((
+                    (clue.number, direction_name(&clue.direction)),
+                    answer.to_string(),
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_metadata() {
+        let imported = parse_xd(XD.as_bytes()).unwrap();
+        assert_eq!(imported.title, "Test Puzzle");
+        assert_eq!(imported.author, "A. Setter");
+        assert_eq!(
+            imported.notes,
+            Some("Circled squares are special".to_string())
+        );
+        assert_eq!((imported.width, imported.height), (3, 3));
+    }
+
+    #[test]
+    fn parses_rebus_and_special_squares() {
+        let imported = parse_xd(XD.as_bytes()).unwrap();
+        assert_eq!(imported.cells[3], Some("HAT".to_string()));
+        assert_eq!(imported.cells[4], None);
+        assert_eq!(imported.cells[8], Some("R".to_string()));
+        assert_eq!(imported.circled_cells, vec![Position { x: 2, y: 2 }]);
+    }
+
+    #[test]
+    fn parses_clues_without_their_answers() {
+        let imported = parse_xd(XD.as_bytes()).unwrap();
+        assert_eq!(imported.clues.len(), 4);
+        let down = &imported.clues[2];
+        assert_eq!(
+            (down.number, &down.direction),
+            (1, &GuardianDirection::Down)
+        );
+        assert_eq!(down.clue, "Revolutionary taking headgear (1,4)");
+        assert_eq!(down.separators, vec![1]);
+    }
+
+    #[test]
+    fn stored_answers_match_the_clue_answers() {
+        let guardian_crossword =
+            to_guardian_crossword(parse_xd(XD.as_bytes()).unwrap(), "mine").unwrap();
+        let answers = clue_answers(XD);
+        let grid = solution_grid(&guardian_crossword);
+        let width = guardian_crossword.dimensions.cols;
+        assert_eq!(guardian_crossword.entries.len(), answers.len());
+        for entry in &guardian_crossword.entries {
+            let stored: String = entry_cells(entry)
+                .into_iter()
+                .filter_map(|(x, y)| grid[(x + y * width) as usize].clone())
+                .collect();
+            assert_eq!(
+                Some(&stored),
+                answers.get(&(entry.number, direction_name(&entry.direction)))
+            );
+        }
+        assert_eq!(guardian_crossword.name, "Test Puzzle");
+        assert_eq!(
+            guardian_crossword.circled_cells,
+            vec![Position { x: 2, y: 2 }]
+        );
+        assert_eq!(guardian_crossword.rebus_cells.len(), 1);
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let imported = parse_xd(XD.as_bytes()).unwrap();
+        let guardian_crossword = to_guardian_crossword(imported.clone(), "mine").unwrap();
+        let xd = to_xd(&guardian_crossword).unwrap();
+        assert_eq!(parse_xd(xd.as_bytes()).unwrap(), imported);
+    }
+
+    #[test]
+    fn needs_the_solution_to_write() {
+        let mut guardian_crossword =
+            to_guardian_crossword(parse_xd(XD.as_bytes()).unwrap(), "mine").unwrap();
+        guardian_crossword.entries[0].solution = String::new();
+        guardian_crossword.rebus_cells.clear();
+        assert!(matches!(
+            to_xd(&guardian_crossword),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn skips_section_headings() {
+        let with_headings = format!(
+            "## Metadata\n\n{}",
+            XD.replace("\n\nCAT", "\n\n## Grid\nCAT")
+        );
+        assert_eq!(
+            parse_xd(with_headings.as_bytes()).unwrap(),
+            parse_xd(XD.as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn keeps_lines_after_the_clues_as_notes() {
+        let xd = "CAT\nA#O\nEAR\n\nA1. Feline (3) ~ CAT\n\nSet for testing";
+        let imported = parse_xd(xd.as_bytes()).unwrap();
+        assert_eq!(imported.title, "");
+        assert_eq!(imported.clues.len(), 1);
+        assert_eq!(imported.notes, Some("Set for testing".to_string()));
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        for xd in [
+            "",
+            "Title: No grid\n",
+            "CAT\nA#\nEAR\n",
+            "Rebus: 1HAT\n\nC1T\n",
+            "Rebus: 12=HAT\n\nC1T\n",
+        ] {
+            assert!(
+                matches!(parse_xd(xd.as_bytes()), Err(AppError::BadRequest(_))),
+                "{:?} should be rejected",
+                xd
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_grids_with_missing_clues() {
+        let imported = parse_xd("CAT\nA#O\nEAR\n\nA1. Feline (3) ~ CAT\n".as_bytes()).unwrap();
+        assert!(matches!(
+            to_guardian_crossword(imported, "mine"),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::models::errors::{to_status_code, AppError};
 use actix::{Actor, Addr};
 use actix_cors::Cors;
-use actix_web::web::{Data, Path, Payload};
+use actix_web::web::{Bytes, Data, Json, Path, Payload, Query};
 use actix_web::{
     get, middleware, post, App, Error, HttpRequest, HttpResponse, HttpServer, Responder,
 };
@@ -9,11 +9,29 @@ use actix_web_actors::ws::start;
 use diesel::r2d2;
 use diesel::PgConnection;
 use std::io::ErrorKind;
+use std::sync::Arc;
 
-use crate::services::crossword_db_actions::{get_crossword_for_series_and_id, get_crossword_metadata_for_series, get_guardian_crossword_for_series_and_id};
-use crate::services::ws_server::MoveServer;
+use crate::config::DEFAULT_SERIES;
+use crate::models::api_models::{
+    BackfillRequest, ConnectionQuery, Direction, ExportQuery, GridScope, ImportFormat, ImportQuery,
+    PageQuery, RevealQuery, ScrapeTrigger,
+};
+use crate::models::guardian::GuardianCrossword;
+use crate::services::auth_service::{is_admin, is_admin_token, require_admin};
+use crate::services::crossword_db_actions::{
+    get_crossword_for_id, get_crossword_for_series_and_id, get_crossword_metadata_for_series,
+    get_guardian_crossword_for_id,
+};
+use crate::services::crossword_source::CrosswordSource;
+use crate::services::guardian_source::GuardianSource;
+use crate::services::scrape_run_service::get_scrape_run_page;
+use crate::services::scrape_scheduler::{parse_schedules, GetScheduleStatus, ScrapeScheduler};
+use crate::services::ws_server::{GetActiveSince, MoveServer, Reveal};
 use crate::services::ws_session::WsSession;
 
+mod cli;
+mod config;
+mod formats;
 mod models;
 mod schema;
 mod services;
@@ -25,19 +43,56 @@ async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "actix_web=trace");
     env_logger::init();
     dotenv::dotenv().ok();
-    println!("Starting server");
     let pool = initialize_db_pool()?;
+    let scraper_config = config::scraper_config().map_err(config_error)?;
+    let source: Arc<dyn CrosswordSource> =
+        Arc::new(GuardianSource::new(&scraper_config).map_err(|e| config_error(e.to_string()))?);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(pool, source, &args).await;
+    }
+    println!("Starting server");
     let server = MoveServer::new(pool.clone()).start();
+    let scheduler = ScrapeScheduler::new(
+        pool.clone(),
+        source.clone(),
+        parse_schedules(config::scrape_schedules()).map_err(config_error)?,
+        config::scrape_schedule_timezone().map_err(config_error)?,
+        config::scrape_schedule_jitter().map_err(config_error)?,
+    )
+    .start();
     HttpServer::new(move || {
         App::new()
             .wrap(Cors::default().allow_any_method().allow_any_origin())
             .wrap(middleware::Logger::default())
             .app_data(Data::new(pool.clone()))
             .app_data(Data::new(server.clone()))
+            .app_data(Data::new(scheduler.clone()))
+            .app_data(Data::from(source.clone()))
+            .service(import_crossword)
+            .service(export_crossword_ipuz)
+            .service(export_crossword_puz)
+            .service(export_crossword_xd)
             .service(get_crossword_data)
             .service(get_all_crossword_data)
+            .service(get_crossword_data_for_series)
+            .service(get_all_crossword_data_for_series)
             .service(get_crossword_data_guardian)
+            .service(get_crossword_data_guardian_public)
             .service(update_crosswords)
+            .service(backfill_crosswords)
+            .service(get_scheduler_status)
+            .service(get_scrape_runs)
+            .service(check_grid)
+            .service(check_cell)
+            .service(check_entry)
+            .service(reveal_grid)
+            .service(reveal_cell)
+            .service(reveal_entry)
+            .service(get_team_stats)
+            .service(get_contributions)
+            .service(get_leaderboard)
+            .service(get_replay)
             .service(start_connection)
     })
     .bind(std::env::var("HOST_PORT").unwrap_or("127.0.0.1:8080".to_string()))?
@@ -46,52 +101,456 @@ async fn main() -> std::io::Result<()> {
 }
 
 #[post("/update-crosswords")]
-async fn update_crosswords(pool: Data<DbPool>) -> impl Responder {
-    let result = services::crossword_service::update_crosswords(pool).await;
-    return match result {
-        Ok(message) => HttpResponse::Ok().body(message),
+async fn update_crosswords(
+    pool: Data<DbPool>,
+    source: Data<dyn CrosswordSource>,
+) -> impl Responder {
+    let result = services::crossword_service::update_crosswords(
+        pool,
+        source.into_inner(),
+        config::scrape_series(),
+        ScrapeTrigger::Manual,
+    )
+    .await;
+    match result {
+        Ok(report) => serde_json::to_string(&report).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse scrape report to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
         Err(error) => build_error_response(error),
-    };
+    }
+}
+
+#[post("/admin/backfill")]
+async fn backfill_crosswords(
+    req: HttpRequest,
+    pool: Data<DbPool>,
+    source: Data<dyn CrosswordSource>,
+    body: Json<BackfillRequest>,
+) -> impl Responder {
+    if let Err(error) = require_admin(&req) {
+        return build_error_response(error);
+    }
+    let request = body.into_inner();
+    let result = services::crossword_service::backfill_crosswords(
+        pool,
+        source.into_inner(),
+        request.series.to_lowercase(),
+        request.from,
+        request.to,
+    )
+    .await;
+    match result {
+        Ok(report) => serde_json::to_string(&report).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse backfill report to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/admin/scheduler")]
+async fn get_scheduler_status(
+    req: HttpRequest,
+    scheduler: Data<Addr<ScrapeScheduler>>,
+) -> impl Responder {
+    if let Err(error) = require_admin(&req) {
+        return build_error_response(error);
+    }
+    match scheduler.send(GetScheduleStatus).await {
+        Ok(statuses) => serde_json::to_string(&statuses).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse scheduler status to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(AppError::InternalServerError(error.to_string())),
+    }
+}
+
+#[get("/admin/scrape-runs")]
+async fn get_scrape_runs(
+    req: HttpRequest,
+    pool: Data<DbPool>,
+    query: Query<PageQuery>,
+) -> impl Responder {
+    if let Err(error) = require_admin(&req) {
+        return build_error_response(error);
+    }
+    let result = get_scrape_run_page(pool, query.into_inner()).await;
+    match result {
+        Ok(page) => serde_json::to_string(&page).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse scrape runs to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[post("/crosswords/import")]
+async fn import_crossword(
+    pool: Data<DbPool>,
+    query: Query<ImportQuery>,
+    body: Bytes,
+) -> impl Responder {
+    let query = query.into_inner();
+    let result = services::import_service::import_crossword(
+        pool,
+        query.series,
+        query.format.unwrap_or(ImportFormat::Puz),
+        &body,
+    )
+    .await;
+    match result {
+        Ok(metadata) => serde_json::to_string(&metadata).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse metadata to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/crossword/{id}.ipuz")]
+async fn export_crossword_ipuz(
+    req: HttpRequest,
+    pool: Data<DbPool>,
+    path: Path<(String,)>,
+    query: Query<ExportQuery>,
+) -> impl Responder {
+    let crossword_id = path.into_inner().0;
+    let include_solution = query.solution.unwrap_or(false);
+    let result =
+        services::export_service::export_ipuz(pool, crossword_id, include_solution, is_admin(&req))
+            .await;
+    match result {
+        Ok(ipuz) => serde_json::to_string(&ipuz).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse ipuz to a string"),
+            |x| HttpResponse::Ok().content_type("application/json").body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/crossword/{id}.puz")]
+async fn export_crossword_puz(
+    req: HttpRequest,
+    pool: Data<DbPool>,
+    path: Path<(String,)>,
+    query: Query<ExportQuery>,
+) -> impl Responder {
+    let crossword_id = path.into_inner().0;
+    let result = services::export_service::export_puz(
+        pool,
+        crossword_id.clone(),
+        query.into_inner().team,
+        is_admin(&req),
+    )
+    .await;
+    match result {
+        Ok(puz) => HttpResponse::Ok()
+            .content_type("application/x-crossword")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.puz\"", crossword_id),
+            ))
+            .body(puz),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/crossword/{id}.xd")]
+async fn export_crossword_xd(
+    req: HttpRequest,
+    pool: Data<DbPool>,
+    path: Path<(String,)>,
+) -> impl Responder {
+    let crossword_id = path.into_inner().0;
+    let result =
+        services::export_service::export_xd(pool, crossword_id.clone(), is_admin(&req)).await;
+    match result {
+        Ok(xd) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.xd\"", crossword_id),
+            ))
+            .body(xd),
+        Err(error) => build_error_response(error),
+    }
 }
 
 #[get("/crossword/{id}")]
 async fn get_crossword_data(pool: Data<DbPool>, path: Path<(String,)>) -> impl Responder {
     let crossword_id = path.into_inner().0;
-    let crossword_data =
-        get_crossword_for_series_and_id(pool, crossword_id, "cryptic".to_string()).await;
-    return match crossword_data {
+    let crossword_data = get_crossword_for_id(pool, crossword_id).await;
+    match crossword_data {
         Ok(message) => serde_json::to_string(&message).map_or(
             HttpResponse::BadRequest().body("Couldn't parse crossword to a string"),
             |x| HttpResponse::Ok().body(x),
         ),
         Err(error) => build_error_response(error),
-    };
+    }
+}
+
+#[get("/series/{series}/crossword/{id}")]
+async fn get_crossword_data_for_series(
+    pool: Data<DbPool>,
+    path: Path<(String, String)>,
+) -> impl Responder {
+    let (series, crossword_id) = path.into_inner();
+    crossword_response(pool, crossword_id, series).await
+}
+
+async fn crossword_response(
+    pool: Data<DbPool>,
+    crossword_id: String,
+    series: String,
+) -> HttpResponse {
+    let crossword_data = get_crossword_for_series_and_id(pool, crossword_id, series).await;
+    match crossword_data {
+        Ok(message) => serde_json::to_string(&message).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse crossword to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
 }
 
 #[get("/crossword/{id}/guardian")]
-async fn get_crossword_data_guardian(pool: Data<DbPool>, path: Path<(String,)>) -> impl Responder {
+async fn get_crossword_data_guardian(
+    req: HttpRequest,
+    pool: Data<DbPool>,
+    path: Path<(String,)>,
+) -> impl Responder {
+    if let Err(error) = require_admin(&req) {
+        return build_error_response(error);
+    }
+    let crossword_id = path.into_inner().0;
+    let crossword_data = get_guardian_crossword_for_id(pool, crossword_id).await;
+    guardian_crossword_response(crossword_data)
+}
+
+#[get("/crossword/{id}/guardian/public")]
+async fn get_crossword_data_guardian_public(
+    pool: Data<DbPool>,
+    path: Path<(String,)>,
+) -> impl Responder {
     let crossword_id = path.into_inner().0;
     let crossword_data =
-        get_guardian_crossword_for_series_and_id(pool, crossword_id, "cryptic".to_string()).await;
-    return match crossword_data {
+        services::crossword_service::get_public_guardian_crossword(pool, crossword_id).await;
+    guardian_crossword_response(crossword_data)
+}
+
+fn guardian_crossword_response(
+    crossword_data: Result<GuardianCrossword, AppError>,
+) -> HttpResponse {
+    match crossword_data {
         Ok(message) => serde_json::to_string(&message).map_or(
             HttpResponse::BadRequest().body("Couldn't parse crossword to a string"),
             |x| HttpResponse::Ok().body(x),
         ),
         Err(error) => build_error_response(error),
-    };
+    }
 }
 
 #[get("/crosswords")]
 async fn get_all_crossword_data(pool: Data<DbPool>) -> impl Responder {
-    let crossword_data = get_crossword_metadata_for_series(pool, "cryptic".to_string()).await;
-    return match crossword_data {
+    crossword_metadata_response(pool, DEFAULT_SERIES.to_string()).await
+}
+
+#[get("/series/{series}/crosswords")]
+async fn get_all_crossword_data_for_series(
+    pool: Data<DbPool>,
+    path: Path<(String,)>,
+) -> impl Responder {
+    let series = path.into_inner().0;
+    crossword_metadata_response(pool, series).await
+}
+
+async fn crossword_metadata_response(pool: Data<DbPool>, series: String) -> HttpResponse {
+    let crossword_data = get_crossword_metadata_for_series(pool, series).await;
+    match crossword_data {
         Ok(message) => serde_json::to_string(&message).map_or(
             HttpResponse::BadRequest().body("Couldn't parse metadata to a string"),
             |x| HttpResponse::Ok().body(x),
         ),
         Err(error) => build_error_response(error),
+    }
+}
+
+#[post("/teams/{team_id}/crossword/{crossword_id}/check")]
+async fn check_grid(pool: Data<DbPool>, path: Path<(String, String)>) -> impl Responder {
+    let (team_id, crossword_id) = path.into_inner();
+    check_response(pool, team_id, crossword_id, GridScope::Grid).await
+}
+
+#[post("/teams/{team_id}/crossword/{crossword_id}/check/cell/{x}/{y}")]
+async fn check_cell(pool: Data<DbPool>, path: Path<(String, String, i64, i64)>) -> impl Responder {
+    let (team_id, crossword_id, x, y) = path.into_inner();
+    check_response(pool, team_id, crossword_id, GridScope::Cell { x, y }).await
+}
+
+#[post("/teams/{team_id}/crossword/{crossword_id}/check/entry/{number}/{direction}")]
+async fn check_entry(
+    pool: Data<DbPool>,
+    path: Path<(String, String, i64, Direction)>,
+) -> impl Responder {
+    let (team_id, crossword_id, number, direction) = path.into_inner();
+    check_response(
+        pool,
+        team_id,
+        crossword_id,
+        GridScope::Entry { number, direction },
+    )
+    .await
+}
+
+async fn check_response(
+    pool: Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+    scope: GridScope,
+) -> HttpResponse {
+    let result = services::check_service::check_solution(pool, team_id, crossword_id, scope).await;
+    match result {
+        Ok(check_result) => serde_json::to_string(&check_result).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse check result to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[post("/teams/{team_id}/crossword/{crossword_id}/reveal")]
+async fn reveal_grid(
+    srv: Data<Addr<MoveServer>>,
+    path: Path<(String, String)>,
+    query: Query<RevealQuery>,
+) -> impl Responder {
+    let (team_id, crossword_id) = path.into_inner();
+    reveal_response(
+        srv,
+        team_id,
+        crossword_id,
+        query.into_inner().user,
+        GridScope::Grid,
+    )
+    .await
+}
+
+#[post("/teams/{team_id}/crossword/{crossword_id}/reveal/cell/{x}/{y}")]
+async fn reveal_cell(
+    srv: Data<Addr<MoveServer>>,
+    path: Path<(String, String, i64, i64)>,
+    query: Query<RevealQuery>,
+) -> impl Responder {
+    let (team_id, crossword_id, x, y) = path.into_inner();
+    let scope = GridScope::Cell { x, y };
+    reveal_response(srv, team_id, crossword_id, query.into_inner().user, scope).await
+}
+
+#[post("/teams/{team_id}/crossword/{crossword_id}/reveal/entry/{number}/{direction}")]
+async fn reveal_entry(
+    srv: Data<Addr<MoveServer>>,
+    path: Path<(String, String, i64, Direction)>,
+    query: Query<RevealQuery>,
+) -> impl Responder {
+    let (team_id, crossword_id, number, direction) = path.into_inner();
+    let scope = GridScope::Entry { number, direction };
+    reveal_response(srv, team_id, crossword_id, query.into_inner().user, scope).await
+}
+
+async fn reveal_response(
+    srv: Data<Addr<MoveServer>>,
+    team_id: String,
+    crossword_id: String,
+    user_id: String,
+    scope: GridScope,
+) -> HttpResponse {
+    let result = srv
+        .send(Reveal {
+            scope,
+            user: user_id,
+            team: team_id,
+            crossword: crossword_id,
+        })
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))
+        .and_then(|result| result);
+    match result {
+        Ok(revealed) => serde_json::to_string(&revealed).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse revealed cells to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/teams/{team_id}/stats")]
+async fn get_team_stats(
+    pool: Data<DbPool>,
+    srv: Data<Addr<MoveServer>>,
+    path: Path<(String,)>,
+) -> impl Responder {
+    let team_id = path.into_inner().0;
+    let active_since = match srv
+        .send(GetActiveSince {
+            team: team_id.clone(),
+        })
+        .await
+    {
+        Ok(active_since) => active_since,
+        Err(error) => {
+            return build_error_response(AppError::InternalServerError(error.to_string()))
+        }
     };
+    let result =
+        services::solve_progress_service::get_team_stats(pool, team_id, active_since).await;
+    match result {
+        Ok(stats) => serde_json::to_string(&stats).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse stats to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/teams/{team_id}/crossword/{crossword_id}/contributions")]
+async fn get_contributions(pool: Data<DbPool>, path: Path<(String, String)>) -> impl Responder {
+    let (team_id, crossword_id) = path.into_inner();
+    let result =
+        services::contribution_service::get_contributions(pool, team_id, crossword_id).await;
+    match result {
+        Ok(contributions) => serde_json::to_string(&contributions).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse contributions to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/teams/{team_id}/leaderboard")]
+async fn get_leaderboard(pool: Data<DbPool>, path: Path<(String,)>) -> impl Responder {
+    let team_id = path.into_inner().0;
+    let result = services::contribution_service::get_leaderboard(pool, team_id).await;
+    match result {
+        Ok(leaderboard) => serde_json::to_string(&leaderboard).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse leaderboard to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
+}
+
+#[get("/teams/{team_id}/crossword/{crossword_id}/replay")]
+async fn get_replay(pool: Data<DbPool>, path: Path<(String, String)>) -> impl Responder {
+    let (team_id, crossword_id) = path.into_inner();
+    let result = services::replay_service::get_replay(pool, team_id, crossword_id).await;
+    match result {
+        Ok(replay) => serde_json::to_string(&replay).map_or(
+            HttpResponse::BadRequest().body("Couldn't parse replay to a string"),
+            |x| HttpResponse::Ok().body(x),
+        ),
+        Err(error) => build_error_response(error),
+    }
 }
 
 #[get("/move/{team_id}/{crossword_id}/{user_id}")]
@@ -99,13 +558,17 @@ pub async fn start_connection(
     req: HttpRequest,
     stream: Payload,
     path: Path<(String, String, String)>,
+    query: Query<ConnectionQuery>,
     srv: Data<Addr<MoveServer>>,
 ) -> Result<HttpResponse, Error> {
+    // browsers can't set headers when opening a websocket, so the token may come as a query param
+    let admin = is_admin(&req) || is_admin_token(query.token.as_deref());
     let ws = WsSession::new(
         srv.get_ref().clone(),
         path.2.clone(),
         path.clone().0,
         path.1.clone(),
+        admin,
     );
     start(ws, &req, stream)
 }
@@ -114,18 +577,25 @@ fn build_error_response(error: AppError) -> HttpResponse {
     HttpResponse::build(to_status_code(error.clone())).body(error.clone().to_string())
 }
 
+fn config_error(message: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidInput, message)
+}
+
 fn initialize_db_pool() -> std::io::Result<DbPool> {
     let conn_spec = std::env::var("DATABASE_URL").map_err(|e| {
         std::io::Error::new(
             ErrorKind::ConnectionAborted,
-            format!("Cannot read env variable DATABASE_URL: {}", e.to_string()),
+            format!("Cannot read env variable DATABASE_URL: {}", e),
         )
     })?;
     let manager = r2d2::ConnectionManager::<PgConnection>::new(conn_spec);
-    r2d2::Pool::builder().max_size(5).build(manager).map_err(|e| {
-        std::io::Error::new(
-            ErrorKind::ConnectionAborted,
-            format!("Cannot connect to database: {}", e.to_string()),
-        )
-    })
+    r2d2::Pool::builder()
+        .max_size(5)
+        .build(manager)
+        .map_err(|e| {
+            std::io::Error::new(
+                ErrorKind::ConnectionAborted,
+                format!("Cannot connect to database: {}", e),
+            )
+        })
 }
@@ -6,6 +6,7 @@ use chrono::NaiveDate;
 use diesel::Queryable;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Queryable)]
 #[serde(rename_all = "camelCase")]
@@ -21,6 +22,51 @@ pub struct SolutionItemDto {
     pub x: i64,
     pub y: i64,
     pub value: String,
+    /// Set when the letter was revealed rather than solved, ignored on moves sent by clients
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub revealed: bool,
+    /// The cell's version after the move. On moves sent by clients it is the last version they saw,
+    /// and the move is rejected if the cell has changed since. Moves without one always apply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+    /// Pencilled letters are guesses, which don't count towards checks, completion or contributions
+    #[serde(default, skip_serializing_if = "MarkType::is_pen")]
+    pub mark: MarkType,
+    /// Letters the solver is considering for the cell
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkType {
+    #[default]
+    Pen,
+    Pencil,
+}
+
+impl MarkType {
+    pub fn is_pen(&self) -> bool {
+        *self == MarkType::Pen
+    }
+}
+
+impl fmt::Display for MarkType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MarkType::Pen => write!(f, "pen"),
+            MarkType::Pencil => write!(f, "pencil"),
+        }
+    }
+}
+
+impl From<&str> for MarkType {
+    fn from(mark: &str) -> Self {
+        match mark {
+            "pencil" => MarkType::Pencil,
+            _ => MarkType::Pen,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Queryable)]
@@ -55,10 +101,30 @@ pub struct Clue {
 
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum Direction {
+    #[serde(alias = "across")]
     Across,
+    #[serde(alias = "down")]
     Down,
 }
 
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Across => write!(f, "across"),
+            Direction::Down => write!(f, "down"),
+        }
+    }
+}
+
+impl From<&str> for Direction {
+    fn from(direction: &str) -> Self {
+        match direction {
+            "down" => Direction::Down,
+            _ => Direction::Across,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Cell {
@@ -75,4 +141,462 @@ pub struct CellData {
     pub number: Option<i64>,
     pub clue_id: ClueId,
     pub clue_id_2: Option<ClueId>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub circled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillRequest {
+    pub series: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillReport {
+    pub series: String,
+    pub stored: Vec<i64>,
+    pub skipped: Vec<i64>,
+    pub failed: Vec<FailedCrossword>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedCrossword {
+    pub series_no: i64,
+    pub error: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleStatus {
+    pub series: String,
+    pub schedule: String,
+    pub running: bool,
+    pub next_run_at: Option<i64>,
+    pub last_run_started_at: Option<i64>,
+    pub last_run_finished_at: Option<i64>,
+    pub last_run_succeeded: Option<bool>,
+    pub last_run_error: Option<String>,
+    pub last_run_report: Option<ScrapeReport>,
+    pub skipped_runs: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrapeReport {
+    pub series: Vec<SeriesScrapeReport>,
+}
+
+impl ScrapeReport {
+    /// Describes what went wrong, if listing any series or storing any crossword failed
+    pub fn failure(&self) -> Option<String> {
+        let failures: Vec<String> = self
+            .series
+            .iter()
+            .flat_map(|series| {
+                let failed = series
+                    .crosswords
+                    .iter()
+                    .filter(|crossword| crossword.outcome.is_failure())
+                    .count();
+                let failed = (failed > 0)
+                    .then(|| format!("{}: {} crosswords failed", series.series, failed));
+                series
+                    .error
+                    .as_ref()
+                    .map(|error| format!("{}: {}", series.series, error))
+                    .into_iter()
+                    .chain(failed)
+            })
+            .collect();
+        (!failures.is_empty()).then(|| failures.join(", "))
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesScrapeReport {
+    pub series: String,
+    pub error: Option<String>,
+    pub crosswords: Vec<CrosswordScrapeResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrosswordScrapeResult {
+    pub series_no: i64,
+    pub outcome: ScrapeOutcome,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScrapeOutcome {
+    Stored,
+    AlreadyPresent,
+    FetchError,
+    ParseError,
+    DbError,
+}
+
+impl ScrapeOutcome {
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            ScrapeOutcome::FetchError | ScrapeOutcome::ParseError | ScrapeOutcome::DbError
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScrapeTrigger {
+    Manual,
+    Scheduled,
+    Backfill,
+}
+
+impl fmt::Display for ScrapeTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScrapeTrigger::Manual => write!(f, "manual"),
+            ScrapeTrigger::Scheduled => write!(f, "scheduled"),
+            ScrapeTrigger::Backfill => write!(f, "backfill"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrapeRunDto {
+    pub id: String,
+    pub series: String,
+    pub trigger: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub stored: i64,
+    pub already_present: i64,
+    pub failed: i64,
+    pub error: Option<String>,
+    pub errors: Vec<CrosswordScrapeResult>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageQuery {
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrapeRunPage {
+    pub page: i64,
+    pub page_size: i64,
+    pub total: i64,
+    pub runs: Vec<ScrapeRunDto>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Puz,
+    Ipuz,
+    Xd,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportQuery {
+    pub series: String,
+    pub format: Option<ImportFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub series: String,
+    pub imported: Vec<CrosswordMetadata>,
+    pub failed: Vec<FailedImport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedImport {
+    pub file: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportQuery {
+    pub solution: Option<bool>,
+    pub team: Option<String>,
+}
+
+/// Which cells of a team's solution to check or reveal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "scope", rename_all = "camelCase")]
+pub enum GridScope {
+    Cell { x: i64, y: i64 },
+    Entry { number: i64, direction: Direction },
+    Grid,
+}
+
+/// Whether each filled in cell is right, empty cells are left out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+    pub cells: Vec<CellCheck>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellCheck {
+    pub x: i64,
+    pub y: i64,
+    pub correct: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevealQuery {
+    pub user: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionQuery {
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamStats {
+    pub team: String,
+    pub puzzles: Vec<PuzzleStats>,
+    pub by_series: Vec<GroupStats>,
+    pub by_setter: Vec<GroupStats>,
+}
+
+/// How a team got on with one crossword, times are in milliseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PuzzleStats {
+    pub crossword_id: String,
+    pub series: String,
+    pub series_no: i64,
+    pub setter: String,
+    pub first_move_at: Option<i64>,
+    pub completed_at: Option<i64>,
+    /// Time from the first move to completion
+    pub solve_millis: Option<i64>,
+    /// Time spent with at least one session connected, up to completion
+    pub active_millis: i64,
+    pub reveals: i64,
+    pub checks: i64,
+}
+
+/// Stats for the crosswords of one series or setter, medians are of completed crosswords
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupStats {
+    pub name: String,
+    pub started: i64,
+    pub completed: i64,
+    pub median_solve_millis: Option<i64>,
+    pub median_active_millis: Option<i64>,
+}
+
+/// Who filled in a team's solution, revealed cells aren't counted as filled by anyone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contributions {
+    pub users: Vec<UserContribution>,
+    pub clues: Vec<ClueAttribution>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserContribution {
+    pub user: String,
+    pub cells: i64,
+    /// Entries with every cell filled by this user
+    pub entries: i64,
+    pub revealed_cells: i64,
+}
+
+/// Who solved a clue, which is whoever filled most of its cells once it is complete. Clues with
+/// any revealed cell aren't credited to anyone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClueAttribution {
+    pub number: i64,
+    pub direction: Direction,
+    pub solved_by: Option<String>,
+    pub revealed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Leaderboard {
+    pub team: String,
+    pub users: Vec<LeaderboardEntry>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardEntry {
+    pub user: String,
+    pub puzzles: i64,
+    pub clues_solved: i64,
+    pub cells: i64,
+    pub entries: i64,
+    pub revealed_cells: i64,
+}
+
+/// A team's moves on a crossword in the order they were made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Replay {
+    pub team: String,
+    pub crossword_id: String,
+    pub moves: Vec<ReplayMove>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayMove {
+    pub seq: i64,
+    pub user: String,
+    pub x: i64,
+    pub y: i64,
+    pub value: String,
+    pub revealed: bool,
+    #[serde(skip_serializing_if = "MarkType::is_pen")]
+    pub mark: MarkType,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<String>,
+    /// When the move was made, in epoch millis
+    pub at: i64,
+}
+
+/// A team's shared note on a clue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClueNoteDto {
+    pub clue_id: ClueId,
+    pub text: String,
+    pub highlights: Vec<ClueHighlight>,
+    pub modified_by: String,
+    pub modified_at: i64,
+}
+
+/// Replaces the note on a clue, a note with no text or highlights is removed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClueNoteUpdate {
+    pub clue_id: ClueId,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub highlights: Vec<ClueHighlight>,
+}
+
+/// Marks the characters of the clue from `start` up to `end` as a part of the wordplay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClueHighlight {
+    pub start: i64,
+    pub end: i64,
+    pub kind: HighlightKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HighlightKind {
+    Definition,
+    Indicator,
+    Fodder,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessageDto {
+    pub id: String,
+    pub user: String,
+    pub text: String,
+    /// The clue the message is about, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clue_id: Option<ClueId>,
+    pub sent_at: i64,
+}
+
+/// Commands sent over the websocket besides moves, which are still sent as a bare array of
+/// `SolutionItemDto`s
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WsCommand {
+    Check(GridScope),
+    Reveal(GridScope),
+    /// Streams the team's moves back to the sender only, `speed` times faster than they were made
+    Replay {
+        speed: Option<f64>,
+    },
+    StopReplay,
+    /// Reverts the sender's last move, admins can name another `user` of the team
+    Undo {
+        user: Option<String>,
+    },
+    Redo {
+        user: Option<String>,
+    },
+    SetNote(ClueNoteUpdate),
+    /// Sends a chat message to the team, optionally about a clue
+    #[serde(rename_all = "camelCase")]
+    Chat {
+        text: String,
+        clue_id: Option<ClueId>,
+    },
+}
+
+/// Messages sent over the websocket besides moves, which are still sent as a bare array of
+/// `SolutionItemDto`s
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WsResponse {
+    CheckResult(CheckResult),
+    /// Sent to the whole team once every cell is filled in correctly
+    #[serde(rename_all = "camelCase")]
+    Completed {
+        completed_at: i64,
+    },
+    /// Sent before a replay's moves so the client can clear its grid
+    ReplayStarted {
+        moves: usize,
+    },
+    ReplayFinished,
+    /// The team's notes, sent on connecting
+    Notes {
+        notes: Vec<ClueNoteDto>,
+    },
+    /// Sent to the whole team when someone changes a note
+    Note(ClueNoteDto),
+    /// The latest chat messages, oldest first, sent on connecting
+    ChatHistory {
+        messages: Vec<ChatMessageDto>,
+    },
+    Chat(ChatMessageDto),
+    /// Sent to a client whose moves were made against an old version of the cells, with the
+    /// cells as they are now
+    Rejected {
+        moves: Vec<SolutionItemDto>,
+    },
+    Error {
+        message: String,
+    },
 }
@@ -1,9 +1,10 @@
 #![allow(unused)]
 #![allow(clippy::all)]
 
+use crate::models::api_models::MarkType;
 use crate::schema::crossword;
 use chrono::NaiveDate;
-use diesel::{Insertable, Queryable};
+use diesel::{AsChangeset, Insertable, Queryable};
 use serde::{Deserialize, Serialize};
 #[derive(Queryable, Debug, Clone, Insertable)]
 #[diesel(table_name = crossword)]
@@ -22,6 +23,8 @@ pub struct Solution {
     pub crossword_for: String,
     pub team_for: String,
     pub solution_json: serde_json::Value,
+    /// The last move event included in `solution_json`
+    pub snapshot_seq: i64,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -30,4 +33,93 @@ pub struct SolutionItem {
     pub y: i64,
     pub value: String,
     pub modified_by: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub revealed: bool,
+    /// The move that last wrote the cell, 0 for cells from before the move history
+    #[serde(default)]
+    pub version: i64,
+    #[serde(default, skip_serializing_if = "MarkType::is_pen")]
+    pub mark: MarkType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<String>,
+}
+
+use crate::schema::scrape_run;
+#[derive(Queryable, Debug, Clone, Insertable, AsChangeset)]
+#[diesel(table_name = scrape_run)]
+pub struct ScrapeRun {
+    pub id: String,
+    pub series: String,
+    pub trigger: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub stored: i64,
+    pub already_present: i64,
+    pub failed: i64,
+    pub error: Option<String>,
+    pub errors_json: serde_json::Value,
+}
+
+use crate::schema::solve_progress;
+#[derive(Queryable, Debug, Clone, Insertable, AsChangeset)]
+#[diesel(table_name = solve_progress)]
+pub struct SolveProgress {
+    pub crossword_for: String,
+    pub team_for: String,
+    pub completed_at: Option<i64>,
+    pub first_move_at: Option<i64>,
+    pub active_millis: i64,
+    pub reveals: i64,
+    pub checks: i64,
+}
+
+use crate::schema::move_event;
+#[derive(Queryable, Debug, Clone, Insertable)]
+#[diesel(table_name = move_event)]
+pub struct MoveEvent {
+    pub crossword_for: String,
+    pub team_for: String,
+    pub seq: i64,
+    pub user_for: String,
+    pub x: i64,
+    pub y: i64,
+    pub value: String,
+    pub revealed: bool,
+    pub created_at: i64,
+    /// The first move sent along with this one, which numbers the group for undo and redo
+    pub group_seq: i64,
+    /// Whose undo history the group belongs to, `user_for` is who the cell's value is credited to
+    pub actor: String,
+    /// The group this one undoes or redoes
+    pub reverts: Option<i64>,
+    pub mark: String,
+    pub candidates: Vec<String>,
+}
+
+use crate::schema::clue_note;
+#[derive(Queryable, Debug, Clone, Insertable, AsChangeset)]
+#[diesel(table_name = clue_note)]
+pub struct ClueNote {
+    pub crossword_for: String,
+    pub team_for: String,
+    pub number: i64,
+    pub direction: String,
+    pub text: String,
+    pub highlights: serde_json::Value,
+    pub modified_by: String,
+    pub modified_at: i64,
+}
+
+use crate::schema::chat_message;
+#[derive(Queryable, Debug, Clone, Insertable)]
+#[diesel(table_name = chat_message)]
+pub struct ChatMessage {
+    pub id: String,
+    pub crossword_for: String,
+    pub team_for: String,
+    pub user_for: String,
+    pub text: String,
+    pub clue_number: Option<i64>,
+    pub clue_direction: Option<String>,
+    pub sent_at: i64,
 }
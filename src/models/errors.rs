@@ -8,6 +8,10 @@ use std::num::ParseIntError;
 pub enum AppError {
     InternalServerError(String),
     CrosswordNotFound(String),
+    BadRequest(String),
+    Unauthorized,
+    FetchError(String),
+    ParseError(String),
 }
 
 impl fmt::Display for AppError {
@@ -19,6 +23,18 @@ impl fmt::Display for AppError {
             AppError::CrosswordNotFound(id) => {
                 write!(f, "Could not find crossword for id: {}", id)
             }
+            AppError::BadRequest(message) => {
+                write!(f, "Bad request: {}", message)
+            }
+            AppError::Unauthorized => {
+                write!(f, "Admin access is required")
+            }
+            AppError::FetchError(message) => {
+                write!(f, "Could not fetch crossword: {}", message)
+            }
+            AppError::ParseError(message) => {
+                write!(f, "Could not parse crossword: {}", message)
+            }
         }
     }
 }
@@ -27,6 +43,10 @@ pub fn to_status_code(error: AppError) -> StatusCode {
     match error {
         AppError::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         AppError::CrosswordNotFound(_) => StatusCode::NOT_FOUND,
+        AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+        AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+        AppError::FetchError(_) => StatusCode::BAD_GATEWAY,
+        AppError::ParseError(_) => StatusCode::BAD_GATEWAY,
     }
 }
 
@@ -50,13 +70,13 @@ impl From<r2d2::Error> for AppError {
 
 impl From<reqwest::Error> for AppError {
     fn from(error: reqwest::Error) -> Self {
-        AppError::InternalServerError(error.to_string())
+        AppError::FetchError(error.to_string())
     }
 }
 
 impl From<SelectorErrorKind<'_>> for AppError {
     fn from(error: SelectorErrorKind) -> Self {
-        AppError::InternalServerError(format!("Invalid selector: {}", error.to_string()))
+        AppError::InternalServerError(format!("Invalid selector: {}", error))
     }
 }
 
@@ -71,3 +91,9 @@ impl From<ParseIntError> for AppError {
         AppError::InternalServerError(error.to_string())
     }
 }
+
+impl From<diesel::result::Error> for AppError {
+    fn from(error: diesel::result::Error) -> Self {
+        AppError::InternalServerError(error.to_string())
+    }
+}
@@ -17,6 +17,13 @@ pub struct GuardianCrossword {
     pub crossword_type: String,
     pub pdf: Option<String>,
     pub instructions: Option<String>,
+    /// Cells drawn with a circle, only set for imported crosswords
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub circled_cells: Vec<Position>,
+    /// Cells whose answer is more than one letter, only set for imported crosswords. The entry
+    /// solutions hold the first letter of these
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rebus_cells: Vec<RebusCell>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -71,3 +78,10 @@ pub struct Dimensions {
     pub cols: i64,
     pub rows: i64,
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebusCell {
+    pub position: Position,
+    pub solution: String,
+}
@@ -1,5 +1,31 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    chat_message (id) {
+        id -> Varchar,
+        crossword_for -> Varchar,
+        team_for -> Varchar,
+        user_for -> Varchar,
+        text -> Varchar,
+        clue_number -> Nullable<Int8>,
+        clue_direction -> Nullable<Varchar>,
+        sent_at -> Int8,
+    }
+}
+
+diesel::table! {
+    clue_note (crossword_for, team_for, number, direction) {
+        crossword_for -> Varchar,
+        team_for -> Varchar,
+        number -> Int8,
+        direction -> Varchar,
+        text -> Varchar,
+        highlights -> Jsonb,
+        modified_by -> Varchar,
+        modified_at -> Int8,
+    }
+}
+
 diesel::table! {
     crossword (id) {
         id -> Varchar,
@@ -10,15 +36,76 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    move_event (crossword_for, team_for, seq) {
+        crossword_for -> Varchar,
+        team_for -> Varchar,
+        seq -> Int8,
+        user_for -> Varchar,
+        x -> Int8,
+        y -> Int8,
+        value -> Varchar,
+        revealed -> Bool,
+        created_at -> Int8,
+        group_seq -> Int8,
+        actor -> Varchar,
+        reverts -> Nullable<Int8>,
+        mark -> Varchar,
+        candidates -> Array<Text>,
+    }
+}
+
+diesel::table! {
+    scrape_lease (series) {
+        series -> Varchar,
+        holder -> Varchar,
+        expires_at -> Int8,
+    }
+}
+
+diesel::table! {
+    scrape_run (id) {
+        id -> Varchar,
+        series -> Varchar,
+        trigger -> Varchar,
+        started_at -> Int8,
+        finished_at -> Nullable<Int8>,
+        stored -> Int8,
+        already_present -> Int8,
+        failed -> Int8,
+        error -> Nullable<Varchar>,
+        errors_json -> Jsonb,
+    }
+}
+
+diesel::table! {
+    solve_progress (crossword_for, team_for) {
+        crossword_for -> Varchar,
+        team_for -> Varchar,
+        completed_at -> Nullable<Int8>,
+        first_move_at -> Nullable<Int8>,
+        active_millis -> Int8,
+        reveals -> Int8,
+        checks -> Int8,
+    }
+}
+
 diesel::table! {
     solution (crossword_for, team_for) {
         crossword_for -> Varchar,
         team_for -> Varchar,
         solution_json -> Jsonb,
+        snapshot_seq -> Int8,
     }
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    chat_message,
+    clue_note,
     crossword,
+    move_event,
+    scrape_lease,
+    scrape_run,
     solution,
+    solve_progress,
 );
@@ -0,0 +1,29 @@
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::HttpRequest;
+
+use crate::config::admin_token;
+use crate::models::errors::AppError;
+
+pub fn is_admin(req: &HttpRequest) -> bool {
+    is_admin_token(
+        req.headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer ")),
+    )
+}
+
+pub fn is_admin_token(provided_token: Option<&str>) -> bool {
+    match (admin_token(), provided_token) {
+        (Some(token), Some(provided)) => token == provided,
+        _ => false,
+    }
+}
+
+pub fn require_admin(req: &HttpRequest) -> Result<(), AppError> {
+    if is_admin(req) {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized)
+    }
+}
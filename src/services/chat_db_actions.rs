@@ -0,0 +1,43 @@
+use actix_web::web;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+use crate::models::db_models::ChatMessage;
+use crate::models::errors::AppError;
+use crate::schema::chat_message::dsl::{chat_message, crossword_for, id, sent_at, team_for};
+use crate::DbPool;
+
+/// Gives the team's latest messages on the crossword, newest first
+pub async fn get_latest_chat_messages(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+    limit: i64,
+) -> actix_web::Result<Vec<ChatMessage>, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        chat_message
+            .filter(crossword_for.eq(crossword_id))
+            .filter(team_for.eq(team_id))
+            .order((sent_at.desc(), id.desc()))
+            .limit(limit)
+            .load(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn store_chat_message(
+    pool: web::Data<DbPool>,
+    message: ChatMessage,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        diesel::insert_into(chat_message)
+            .values(&message)
+            .execute(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
@@ -0,0 +1,85 @@
+use actix_web::web;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::models::api_models::{ChatMessageDto, ClueId, Direction};
+use crate::models::db_models::ChatMessage;
+use crate::models::errors::AppError;
+use crate::services::chat_db_actions::{get_latest_chat_messages, store_chat_message};
+use crate::services::clue_note_service::find_entry;
+use crate::services::crossword_db_actions::get_guardian_crossword_for_id;
+use crate::DbPool;
+
+/// How many of the latest messages are sent when joining a crossword
+const CHAT_HISTORY_LIMIT: i64 = 100;
+const MAX_MESSAGE_LENGTH: usize = 2_000;
+
+/// The team's latest messages on the crossword, oldest first
+pub async fn get_chat_history(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Vec<ChatMessageDto>, AppError> {
+    let mut messages = get_latest_chat_messages(pool, crossword_id, team_id, CHAT_HISTORY_LIMIT)
+        .await?
+        .into_iter()
+        .map(to_chat_message_dto)
+        .collect::<Vec<ChatMessageDto>>();
+    messages.reverse();
+    Ok(messages)
+}
+
+/// Stores a message from the user, checking any clue it refers to is in the crossword
+pub async fn send_chat_message(
+    pool: web::Data<DbPool>,
+    text: String,
+    clue_id: Option<ClueId>,
+    user_id: String,
+    team_id: String,
+    crossword_id: String,
+) -> Result<ChatMessageDto, AppError> {
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err(AppError::BadRequest("Messages can't be empty".to_string()));
+    }
+    if text.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Messages can be at most {} characters",
+            MAX_MESSAGE_LENGTH
+        )));
+    }
+    if let Some(clue_id) = &clue_id {
+        let guardian_crossword =
+            get_guardian_crossword_for_id(pool.clone(), crossword_id.clone()).await?;
+        find_entry(&guardian_crossword, clue_id)?;
+    }
+    let message = ChatMessage {
+        id: Uuid::new_v4().to_string(),
+        crossword_for: crossword_id,
+        team_for: team_id,
+        user_for: user_id,
+        text,
+        clue_number: clue_id.as_ref().map(|clue_id| clue_id.number),
+        clue_direction: clue_id.map(|clue_id| clue_id.direction.to_string()),
+        sent_at: Utc::now().timestamp_millis(),
+    };
+    store_chat_message(pool, message.clone()).await?;
+    Ok(to_chat_message_dto(message))
+}
+
+fn to_chat_message_dto(message: ChatMessage) -> ChatMessageDto {
+    let clue_id = message
+        .clue_number
+        .zip(message.clue_direction)
+        .map(|(number, direction)| ClueId {
+            number,
+            direction: Direction::from(direction.as_str()),
+        });
+    ChatMessageDto {
+        id: message.id,
+        user: message.user_for,
+        text: message.text,
+        clue_id,
+        sent_at: message.sent_at,
+    }
+}
@@ -0,0 +1,122 @@
+use actix_web::web;
+use std::collections::HashMap;
+
+use crate::formats::grid::{entry_cells, solution_grid};
+use crate::models::api_models::{CellCheck, CheckResult, GridScope};
+use crate::models::errors::AppError;
+use crate::models::guardian::GuardianCrossword;
+use crate::services::crossword_db_actions::get_guardian_crossword_for_id;
+use crate::services::crossword_service::{guardian_to_dto_direction, solution_released};
+use crate::services::solution_db_actions::get_solution;
+use crate::services::solve_progress_service::record_check;
+use crate::DbPool;
+
+/// Compares a team's solution with the answers without sending the answers anywhere
+pub async fn check_solution(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+    scope: GridScope,
+) -> Result<CheckResult, AppError> {
+    let guardian_crossword =
+        get_guardian_crossword_for_id(pool.clone(), crossword_id.clone()).await?;
+    ensure_released(&guardian_crossword)?;
+    let entered: HashMap<(i64, i64), String> =
+        get_solution(pool.clone(), crossword_id.clone(), team_id.clone())
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| item.mark.is_pen())
+            .map(|item| ((item.x, item.y), item.value))
+            .collect();
+    let answers = solution_grid(&guardian_crossword);
+    let width = guardian_crossword.dimensions.cols;
+
+    let mut cells = Vec::new();
+    for (x, y) in cells_in_scope(&guardian_crossword, &scope)? {
+        let answer = answer_at(&answers, width, x, y)?;
+        let Some(value) = entered
+            .get(&(x, y))
+            .map(|value| value.trim().to_uppercase())
+            .filter(|value| !value.is_empty())
+        else {
+            continue;
+        };
+        cells.push(CellCheck {
+            x,
+            y,
+            correct: is_correct(&value, &answer),
+        });
+    }
+    record_check(pool, team_id, crossword_id).await;
+    Ok(CheckResult { cells })
+}
+
+/// The positions of the white cells a check covers, in grid order for the whole grid
+pub fn cells_in_scope(
+    guardian_crossword: &GuardianCrossword,
+    scope: &GridScope,
+) -> Result<Vec<(i64, i64)>, AppError> {
+    match scope {
+        GridScope::Cell { x, y } => Ok(vec![(*x, *y)]),
+        GridScope::Entry { number, direction } => {
+            let entry = guardian_crossword
+                .entries
+                .iter()
+                .find(|entry| {
+                    entry.number == *number
+                        && guardian_to_dto_direction(entry.direction.clone()) == *direction
+                })
+                .ok_or_else(|| {
+                    AppError::BadRequest(format!("There is no {} {:?} entry", number, direction))
+                })?;
+            Ok(entry_cells(entry))
+        }
+        GridScope::Grid => {
+            let width = guardian_crossword.dimensions.cols;
+            Ok(solution_grid(guardian_crossword)
+                .iter()
+                .enumerate()
+                .filter(|(_, answer)| answer.is_some())
+                .map(|(index, _)| (index as i64 % width, index as i64 / width))
+                .collect())
+        }
+    }
+}
+
+/// Checking and revealing give answers away, so wait until the answers are released
+pub fn ensure_released(guardian_crossword: &GuardianCrossword) -> Result<(), AppError> {
+    if solution_released(guardian_crossword) {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(
+            "Answers aren't available for this crossword yet".to_string(),
+        ))
+    }
+}
+
+/// The answer for a white cell, failing if the cell is black or its answer isn't known yet
+pub fn answer_at(
+    answers: &[Option<String>],
+    width: i64,
+    x: i64,
+    y: i64,
+) -> Result<String, AppError> {
+    let answer = (0..width)
+        .contains(&x)
+        .then(|| answers.get((x + y * width) as usize).cloned().flatten())
+        .flatten()
+        .ok_or_else(|| AppError::BadRequest(format!("There is no white cell at {},{}", x, y)))?;
+    if answer.is_empty() {
+        return Err(AppError::BadRequest(
+            "Answers aren't available for this crossword yet".to_string(),
+        ));
+    }
+    Ok(answer)
+}
+
+/// Rebus cells can be filled with the whole answer or just its first letter
+pub fn is_correct(value: &str, answer: &str) -> bool {
+    let answer = answer.to_uppercase();
+    value == answer || (answer.chars().count() > 1 && value.chars().eq(answer.chars().take(1)))
+}
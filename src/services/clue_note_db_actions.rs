@@ -0,0 +1,70 @@
+use actix_web::web;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+use crate::models::db_models::ClueNote;
+use crate::models::errors::AppError;
+use crate::schema::clue_note::dsl::{
+    clue_note, crossword_for, direction, highlights, modified_at, modified_by, number, team_for,
+    text,
+};
+use crate::DbPool;
+
+pub async fn get_clue_notes(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+) -> actix_web::Result<Vec<ClueNote>, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        clue_note
+            .filter(crossword_for.eq(crossword_id))
+            .filter(team_for.eq(team_id))
+            .order((direction.asc(), number.asc()))
+            .load(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn store_clue_note(
+    pool: web::Data<DbPool>,
+    note: ClueNote,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        diesel::insert_into(clue_note)
+            .values(&note)
+            .on_conflict((crossword_for, team_for, number, direction))
+            .do_update()
+            .set((
+                text.eq(&note.text),
+                highlights.eq(&note.highlights),
+                modified_by.eq(&note.modified_by),
+                modified_at.eq(note.modified_at),
+            ))
+            .execute(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn delete_clue_note(
+    pool: web::Data<DbPool>,
+    note: ClueNote,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        diesel::delete(clue_note.find((
+            note.crossword_for,
+            note.team_for,
+            note.number,
+            note.direction,
+        )))
+        .execute(&mut conn)
+        .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
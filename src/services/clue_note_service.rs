@@ -0,0 +1,96 @@
+use actix_web::web;
+use chrono::Utc;
+
+use crate::models::api_models::{ClueId, ClueNoteDto, ClueNoteUpdate, Direction};
+use crate::models::db_models::ClueNote;
+use crate::models::errors::AppError;
+use crate::models::guardian::{GuardianCrossword, GuardianEntry};
+use crate::services::clue_note_db_actions::{delete_clue_note, get_clue_notes, store_clue_note};
+use crate::services::crossword_db_actions::get_guardian_crossword_for_id;
+use crate::services::crossword_service::guardian_to_dto_direction;
+use crate::DbPool;
+
+pub async fn get_notes(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Vec<ClueNoteDto>, AppError> {
+    get_clue_notes(pool, crossword_id, team_id)
+        .await?
+        .into_iter()
+        .map(to_clue_note_dto)
+        .collect()
+}
+
+/// Replaces the team's note on a clue, checking the clue exists and the highlights fit within it
+pub async fn set_note(
+    pool: web::Data<DbPool>,
+    update: ClueNoteUpdate,
+    user_id: String,
+    team_id: String,
+    crossword_id: String,
+) -> Result<ClueNoteDto, AppError> {
+    let guardian_crossword =
+        get_guardian_crossword_for_id(pool.clone(), crossword_id.clone()).await?;
+    let entry = find_entry(&guardian_crossword, &update.clue_id)?;
+    let clue_length = entry.clue.chars().count() as i64;
+    let outside_clue = update.highlights.iter().find(|highlight| {
+        highlight.start < 0 || highlight.end <= highlight.start || highlight.end > clue_length
+    });
+    if let Some(highlight) = outside_clue {
+        return Err(AppError::BadRequest(format!(
+            "Highlight {}..{} doesn't fit the clue",
+            highlight.start, highlight.end
+        )));
+    }
+
+    let note = ClueNote {
+        crossword_for: crossword_id,
+        team_for: team_id,
+        number: update.clue_id.number,
+        direction: update.clue_id.direction.to_string(),
+        text: update.text.clone(),
+        highlights: serde_json::to_value(&update.highlights)?,
+        modified_by: user_id,
+        modified_at: Utc::now().timestamp_millis(),
+    };
+    if update.text.trim().is_empty() && update.highlights.is_empty() {
+        delete_clue_note(pool, note.clone()).await?;
+    } else {
+        store_clue_note(pool, note.clone()).await?;
+    }
+    to_clue_note_dto(note)
+}
+
+/// The entry for a clue, failing if the crossword doesn't have it
+pub fn find_entry<'a>(
+    guardian_crossword: &'a GuardianCrossword,
+    clue_id: &ClueId,
+) -> Result<&'a GuardianEntry, AppError> {
+    guardian_crossword
+        .entries
+        .iter()
+        .find(|entry| {
+            entry.number == clue_id.number
+                && guardian_to_dto_direction(entry.direction.clone()) == clue_id.direction
+        })
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "There is no {} {:?} entry",
+                clue_id.number, clue_id.direction
+            ))
+        })
+}
+
+fn to_clue_note_dto(note: ClueNote) -> Result<ClueNoteDto, AppError> {
+    Ok(ClueNoteDto {
+        clue_id: ClueId {
+            number: note.number,
+            direction: Direction::from(note.direction.as_str()),
+        },
+        text: note.text,
+        highlights: serde_json::from_value(note.highlights)?,
+        modified_by: note.modified_by,
+        modified_at: note.modified_at,
+    })
+}
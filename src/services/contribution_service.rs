@@ -0,0 +1,183 @@
+use actix_web::web;
+use itertools::Itertools;
+use std::collections::HashMap;
+
+use crate::formats::grid::{entry_cells, solution_grid};
+use crate::models::api_models::{
+    ClueAttribution, Contributions, Leaderboard, LeaderboardEntry, UserContribution,
+};
+use crate::models::db_models::SolutionItem;
+use crate::models::errors::AppError;
+use crate::models::guardian::GuardianCrossword;
+use crate::services::check_service::is_correct;
+use crate::services::crossword_db_actions::{
+    get_crosswords_for_ids, get_guardian_crossword_for_id,
+};
+use crate::services::crossword_service::{guardian_to_dto_direction, solution_released};
+use crate::services::solution_db_actions::{get_solution, get_solutions_for_team};
+use crate::DbPool;
+
+pub async fn get_contributions(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Contributions, AppError> {
+    let guardian_crossword =
+        get_guardian_crossword_for_id(pool.clone(), crossword_id.clone()).await?;
+    let solution_items = get_solution(pool, crossword_id, team_id)
+        .await?
+        .unwrap_or_default();
+    Ok(contributions(&guardian_crossword, &solution_items))
+}
+
+/// Adds up each user's contributions across every crossword the team has a solution for
+pub async fn get_leaderboard(
+    pool: web::Data<DbPool>,
+    team_id: String,
+) -> Result<Leaderboard, AppError> {
+    let solutions = get_solutions_for_team(pool.clone(), team_id.clone()).await?;
+    let crossword_ids = solutions
+        .iter()
+        .map(|(crossword_id, _)| crossword_id.clone())
+        .collect();
+    let guardian_crosswords: HashMap<String, GuardianCrossword> =
+        get_crosswords_for_ids(pool, crossword_ids)
+            .await?
+            .into_iter()
+            .filter_map(|crossword| {
+                serde_json::from_value(crossword.crossword_json)
+                    .ok()
+                    .map(|guardian_crossword| (crossword.id, guardian_crossword))
+            })
+            .collect();
+
+    let mut users: HashMap<String, LeaderboardEntry> = HashMap::new();
+    for (crossword_id, solution_items) in solutions {
+        let Some(guardian_crossword) = guardian_crosswords.get(&crossword_id) else {
+            continue;
+        };
+        let crossword_contributions = contributions(guardian_crossword, &solution_items);
+        for contribution in crossword_contributions.users {
+            let total = leaderboard_entry(&mut users, &contribution.user);
+            total.puzzles += 1;
+            total.cells += contribution.cells;
+            total.entries += contribution.entries;
+            total.revealed_cells += contribution.revealed_cells;
+        }
+        for clue in crossword_contributions.clues {
+            if let Some(solver) = clue.solved_by {
+                leaderboard_entry(&mut users, &solver).clues_solved += 1;
+            }
+        }
+    }
+    let users = users
+        .into_values()
+        .sorted_by(|a, b| {
+            b.clues_solved
+                .cmp(&a.clues_solved)
+                .then(b.cells.cmp(&a.cells))
+                .then(a.user.cmp(&b.user))
+        })
+        .collect();
+    Ok(Leaderboard {
+        team: team_id,
+        users,
+    })
+}
+
+fn leaderboard_entry<'a>(
+    users: &'a mut HashMap<String, LeaderboardEntry>,
+    user: &str,
+) -> &'a mut LeaderboardEntry {
+    users
+        .entry(user.to_string())
+        .or_insert_with(|| LeaderboardEntry {
+            user: user.to_string(),
+            ..Default::default()
+        })
+}
+
+/// Works out who filled in each cell and solved each clue. Clues only count as solved when they're
+/// right, unless the answers aren't released yet in which case filling them in is enough
+pub fn contributions(
+    guardian_crossword: &GuardianCrossword,
+    solution_items: &[SolutionItem],
+) -> Contributions {
+    let released = solution_released(guardian_crossword);
+    let answers = solution_grid(guardian_crossword);
+    let width = guardian_crossword.dimensions.cols;
+    let filled: HashMap<(i64, i64), &SolutionItem> = solution_items
+        .iter()
+        .filter(|item| item.mark.is_pen() && !item.value.trim().is_empty())
+        .map(|item| ((item.x, item.y), item))
+        .collect();
+
+    let mut users: HashMap<String, UserContribution> = HashMap::new();
+    for item in filled.values() {
+        let user = users
+            .entry(item.modified_by.clone())
+            .or_insert_with(|| UserContribution {
+                user: item.modified_by.clone(),
+                ..Default::default()
+            });
+        if item.revealed {
+            user.revealed_cells += 1;
+        } else {
+            user.cells += 1;
+        }
+    }
+
+    let mut clues = Vec::new();
+    for entry in &guardian_crossword.entries {
+        let cells: Vec<((i64, i64), Option<&&SolutionItem>)> = entry_cells(entry)
+            .into_iter()
+            .map(|position| (position, filled.get(&position)))
+            .collect();
+        let revealed = cells
+            .iter()
+            .any(|(_, item)| item.is_some_and(|item| item.revealed));
+        let all_filled = cells.iter().all(|(_, item)| item.is_some());
+        let all_correct = cells.iter().all(|((x, y), item)| {
+            let answer = answers
+                .get((x + y * width) as usize)
+                .cloned()
+                .flatten()
+                .unwrap_or_default();
+            item.is_some_and(|item| {
+                !answer.is_empty() && is_correct(&item.value.trim().to_uppercase(), &answer)
+            })
+        });
+        let fillers: Vec<&String> = cells
+            .iter()
+            .filter_map(|(_, item)| item.map(|item| &item.modified_by))
+            .collect();
+        if all_filled && !revealed && fillers.iter().all_equal() {
+            if let Some(user) = fillers.first().and_then(|user| users.get_mut(*user)) {
+                user.entries += 1;
+            }
+        }
+        let solved = all_filled && !revealed && (all_correct || !released);
+        let solved_by = solved
+            .then(|| {
+                fillers
+                    .iter()
+                    .counts()
+                    .into_iter()
+                    .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+                    .map(|(user, _)| user.to_string())
+            })
+            .flatten();
+        clues.push(ClueAttribution {
+            number: entry.number,
+            direction: guardian_to_dto_direction(entry.direction.clone()),
+            solved_by,
+            revealed,
+        });
+    }
+
+    let users = users
+        .into_values()
+        .sorted_by(|a, b| b.cells.cmp(&a.cells).then(a.user.cmp(&b.user)))
+        .collect();
+    Contributions { users, clues }
+}
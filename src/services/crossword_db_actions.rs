@@ -2,7 +2,8 @@
 
 use actix_web::web;
 use diesel::row::NamedRow;
-use diesel::{ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use diesel::sql_types::Text;
+use diesel::{Connection, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
 use serde_json::Value;
 
 use crate::models::api_models::{Clue, Clues, CrosswordDto, CrosswordMetadata};
@@ -50,7 +51,7 @@ pub async fn get_guardian_crossword_for_series_and_id(
     pool: web::Data<DbPool>,
     id_for: String,
     series_for: String,
-)-> actix_web::Result<GuardianCrossword, AppError> {
+) -> actix_web::Result<GuardianCrossword, AppError> {
     // use web::block to offload blocking Diesel queries without blocking server thread
     let result: Value = web::block(move || {
         let mut conn = pool.get()?;
@@ -61,15 +62,41 @@ pub async fn get_guardian_crossword_for_series_and_id(
             .first(&mut conn)
             .map_err(|_| AppError::CrosswordNotFound(id_for.clone()))
     })
-        .await??;
+    .await??;
     serde_json::from_value(result).map_err(|e| InternalServerError(e.to_string()))
 }
+pub async fn get_guardian_crossword_for_id(
+    pool: web::Data<DbPool>,
+    id_for: String,
+) -> actix_web::Result<GuardianCrossword, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let result: Value = web::block(move || {
+        let mut conn = pool.get()?;
+        crossword
+            .filter(id.eq(id_for.clone()))
+            .select(crossword_json)
+            .first(&mut conn)
+            .map_err(|_| AppError::CrosswordNotFound(id_for.clone()))
+    })
+    .await??;
+    serde_json::from_value(result).map_err(|e| InternalServerError(e.to_string()))
+}
+
+pub async fn get_crossword_for_id(
+    pool: web::Data<DbPool>,
+    id_for: String,
+) -> actix_web::Result<CrosswordDto, AppError> {
+    let guardian_crossword: GuardianCrossword = get_guardian_crossword_for_id(pool, id_for).await?;
+    Ok(guardian_to_crossword_dto(guardian_crossword))
+}
+
 pub async fn get_crossword_for_series_and_id(
     pool: web::Data<DbPool>,
     id_for: String,
     series_for: String,
 ) -> actix_web::Result<CrosswordDto, AppError> {
-    let guardian_crossword: GuardianCrossword = get_guardian_crossword_for_series_and_id(pool, id_for, series_for).await?;
+    let guardian_crossword: GuardianCrossword =
+        get_guardian_crossword_for_series_and_id(pool, id_for, series_for).await?;
     Ok(guardian_to_crossword_dto(guardian_crossword))
 }
 
@@ -87,3 +114,51 @@ pub async fn store_crosswords(
     })
     .await?
 }
+
+/// Stores the crosswords `number_crosswords` makes given the next free number in the series. The
+/// series is locked until they are stored, so concurrent imports can't take the same numbers
+pub async fn store_numbered_crosswords<F>(
+    pool: web::Data<DbPool>,
+    series_for: String,
+    number_crosswords: F,
+) -> actix_web::Result<Vec<Crossword>, AppError>
+where
+    F: FnOnce(i64) -> Result<Vec<Crossword>, AppError> + Send + 'static,
+{
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction::<_, AppError, _>(|conn| {
+            diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+                .bind::<Text, _>(format!("series/{}", series_for))
+                .execute(conn)?;
+            let last_series_no: Option<i64> = crossword
+                .filter(series.eq(&series_for))
+                .select(diesel::dsl::max(series_no))
+                .first(conn)?;
+            let new_crosswords = number_crosswords(last_series_no.unwrap_or(0) + 1)?;
+            if !new_crosswords.is_empty() {
+                diesel::insert_into(crossword)
+                    .values(&new_crosswords)
+                    .execute(conn)?;
+            }
+            Ok(new_crosswords)
+        })
+    })
+    .await?
+}
+
+pub async fn get_crosswords_for_ids(
+    pool: web::Data<DbPool>,
+    ids: Vec<String>,
+) -> actix_web::Result<Vec<Crossword>, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        crossword
+            .filter(id.eq_any(ids))
+            .load(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
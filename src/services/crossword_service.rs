@@ -2,94 +2,292 @@ extern crate futures;
 extern crate serde;
 
 use actix_web::web;
-use futures::future;
+use chrono::Utc;
+use futures::{stream, StreamExt};
 use itertools::Itertools;
-use scraper::Html;
 use std::collections::HashMap;
-use std::num::ParseIntError;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::models::api_models::Cell::{Black, White};
-use crate::models::api_models::{Cell, CellData, Clue, ClueId, Clues, CrosswordDto, Direction};
+use crate::models::api_models::{
+    BackfillReport, Cell, CellData, Clue, ClueId, Clues, CrosswordDto, CrosswordScrapeResult,
+    Direction, FailedCrossword, ScrapeOutcome, ScrapeReport, ScrapeTrigger, SeriesScrapeReport,
+};
 use crate::models::db_models::Crossword;
 use crate::models::errors::AppError;
-use crate::models::guardian::{GuardianCrossword, GuardianDirection, GuardianEntry};
-use crate::services::crossword_db_actions::{get_crossword_nos_for_series, store_crosswords};
+use crate::models::guardian::{GuardianCrossword, GuardianDirection, GuardianEntry, RebusCell};
+use crate::services::crossword_db_actions::{
+    get_crossword_nos_for_series, get_guardian_crossword_for_id, store_crosswords,
+};
+use crate::services::crossword_source::CrosswordSource;
+use crate::services::scrape_run_db_actions::{release_series_lease, try_lease_series, SeriesLease};
+use crate::services::scrape_run_service::{finish_scrape_run, new_scrape_run, record_scrape_run};
 use crate::DbPool;
 
-pub async fn scrape_crossword(series: &str, id: String) -> Result<GuardianCrossword, AppError> {
-    let url = format!("https://www.theguardian.com/crosswords/{}/{}", series, id);
-    let document = get_document(url).await?;
-    let selector = scraper::Selector::parse(".js-crossword")?;
-    let element = document
-        .select(&selector)
-        .last()
-        .map_or(Err("No element found".to_string()), Ok)?;
-    let json = element
-        .value()
-        .attr("data-crossword-data")
-        .map_or(Err("No attribute found".to_string()), Ok)?;
-    let result = serde_json::from_str(&json)?;
-    Ok(result)
-}
-
-async fn get_document(url: String) -> Result<Html, AppError> {
-    let response = reqwest::get(url).await?.text().await?;
-    Ok(Html::parse_document(&response))
-}
-
-async fn get_recent_crossword_nos(series: &str) -> Result<Vec<i64>, AppError> {
-    let url = format!("https://www.theguardian.com/crosswords/series/{}", series);
-    let series_url = format!("https://www.theguardian.com/crosswords/{}", series);
-    let document = get_document(url).await?;
-    let selector = scraper::Selector::parse(".fc-item__container>a")?;
-    let crossword_nos: Result<Vec<i64>, ParseIntError> = document
-        .select(&selector)
-        .map(|s| s.value().attr("href"))
-        .flatten()
-        .map(|s| s.to_string())
-        .filter(|url| url.starts_with(series_url.as_str()))
-        .map(|url| {
-            url.as_str()
-                .replace(series_url.as_str(), "")
-                .replace("/", "")
-                .parse::<i64>()
-        })
-        .collect();
-    crossword_nos.map_err(|e| AppError::InternalServerError(e.to_string()))
+pub async fn update_crosswords(
+    pool: web::Data<DbPool>,
+    source: Arc<dyn CrosswordSource>,
+    series_to_update: Vec<String>,
+    trigger: ScrapeTrigger,
+) -> Result<ScrapeReport, AppError> {
+    let mut report = ScrapeReport::default();
+    for series in series_to_update {
+        let Some(lease) = try_lease_series(pool.clone(), series.clone()).await? else {
+            report.series.push(SeriesScrapeReport {
+                error: Some(already_scraping(&series)),
+                series,
+                ..Default::default()
+            });
+            continue;
+        };
+        let run = new_scrape_run(&series, trigger);
+        record_scrape_run(pool.clone(), run.clone()).await;
+        let series_report =
+            update_crosswords_for_series(pool.clone(), source.clone(), series).await;
+        let finished_run =
+            finish_scrape_run(run, series_report.error.clone(), &series_report.crosswords);
+        record_scrape_run(pool.clone(), finished_run).await;
+        release_series(pool.clone(), lease, &series_report.series).await;
+        report.series.push(series_report);
+    }
+    Ok(report)
 }
 
-pub async fn update_crosswords(pool: web::Data<DbPool>) -> Result<String, AppError> {
-    let series = "cryptic";
-    let new_crossword_nos: Vec<i64> = get_recent_crossword_nos(series).await?;
-    let existing_crosswords_nos: Vec<i64> =
-        get_crossword_nos_for_series(pool.clone(), series.to_string()).await?;
+fn already_scraping(series: &str) -> String {
+    format!("{} is already being scraped", series)
+}
 
-    let new_crosswords: Result<Vec<Crossword>, serde_json::Error> = future::try_join_all(
-        new_crossword_nos
-            .iter()
-            .filter(|crossword_id| !existing_crosswords_nos.contains(crossword_id))
-            .map(|crossword_id| scrape_crossword(series, crossword_id.to_string())),
+async fn release_series(pool: web::Data<DbPool>, lease: SeriesLease, series: &str) {
+    if let Err(e) = release_series_lease(pool, lease).await {
+        println!("Failed to release lease on {}: {}", series, e);
+    }
+}
+
+async fn update_crosswords_for_series(
+    pool: web::Data<DbPool>,
+    source: Arc<dyn CrosswordSource>,
+    series: String,
+) -> SeriesScrapeReport {
+    let mut report = SeriesScrapeReport {
+        series: series.clone(),
+        ..Default::default()
+    };
+    let new_crossword_nos = match source.list_crossword_nos(&series).await {
+        Ok(crossword_nos) => crossword_nos,
+        Err(error) => {
+            report.error = Some(error.to_string());
+            return report;
+        }
+    };
+    let existing_crosswords_nos =
+        match get_crossword_nos_for_series(pool.clone(), series.clone()).await {
+            Ok(crossword_nos) => crossword_nos,
+            Err(error) => {
+                report.error = Some(error.to_string());
+                return report;
+            }
+        };
+    report.crosswords = scrape_and_store_crosswords(
+        pool,
+        source,
+        &series,
+        new_crossword_nos,
+        existing_crosswords_nos,
+    )
+    .await;
+    report
+}
+
+pub const MAX_BACKFILL_SIZE: i64 = 500;
+
+pub async fn backfill_crosswords(
+    pool: web::Data<DbPool>,
+    source: Arc<dyn CrosswordSource>,
+    series: String,
+    from: i64,
+    to: i64,
+) -> Result<BackfillReport, AppError> {
+    if from > to || from < 1 {
+        return Err(AppError::BadRequest(format!(
+            "Invalid series_no range {} to {}",
+            from, to
+        )));
+    }
+    if to - from >= MAX_BACKFILL_SIZE {
+        return Err(AppError::BadRequest(format!(
+            "Cannot backfill more than {} crosswords at once",
+            MAX_BACKFILL_SIZE
+        )));
+    }
+    let Some(lease) = try_lease_series(pool.clone(), series.clone()).await? else {
+        return Err(AppError::BadRequest(already_scraping(&series)));
+    };
+    let report = backfill_series(pool.clone(), source, series.clone(), from, to).await;
+    release_series(pool, lease, &series).await;
+    report
+}
+
+async fn backfill_series(
+    pool: web::Data<DbPool>,
+    source: Arc<dyn CrosswordSource>,
+    series: String,
+    from: i64,
+    to: i64,
+) -> Result<BackfillReport, AppError> {
+    let run = new_scrape_run(&series, ScrapeTrigger::Backfill);
+    record_scrape_run(pool.clone(), run.clone()).await;
+    let existing_crosswords_nos =
+        match get_crossword_nos_for_series(pool.clone(), series.clone()).await {
+            Ok(crossword_nos) => crossword_nos,
+            Err(error) => {
+                record_scrape_run(
+                    pool.clone(),
+                    finish_scrape_run(run, Some(error.to_string()), &[]),
+                )
+                .await;
+                return Err(error);
+            }
+        };
+    let results = scrape_and_store_crosswords(
+        pool.clone(),
+        source,
+        &series,
+        (from..=to).collect(),
+        existing_crosswords_nos,
     )
-    .await?
-    .iter()
-    .map(|guardian_crossword| {
-        serde_json::to_value(guardian_crossword).map(|json_value| Crossword {
-            id: Uuid::new_v4().to_string(),
-            series: series.to_string(),
-            series_no: guardian_crossword.number,
-            crossword_json: json_value,
-            date: guardian_crossword.date,
-        })
+    .await;
+    record_scrape_run(pool.clone(), finish_scrape_run(run, None, &results)).await;
+    let mut report = BackfillReport {
+        series,
+        ..Default::default()
+    };
+    for result in results {
+        match (result.outcome, result.error) {
+            (ScrapeOutcome::AlreadyPresent, _) => report.skipped.push(result.series_no),
+            (_, Some(error)) => report.failed.push(FailedCrossword {
+                series_no: result.series_no,
+                error,
+            }),
+            (_, None) => report.stored.push(result.series_no),
+        }
+    }
+    Ok(report)
+}
+
+/// Scrapes and stores each crossword not already present, at most `source.concurrency()` at once
+async fn scrape_and_store_crosswords(
+    pool: web::Data<DbPool>,
+    source: Arc<dyn CrosswordSource>,
+    series: &str,
+    crossword_nos: Vec<i64>,
+    existing_crosswords_nos: Vec<i64>,
+) -> Vec<CrosswordScrapeResult> {
+    let concurrency = source.concurrency();
+    stream::iter(crossword_nos.into_iter().map(|series_no| {
+        let already_present = existing_crosswords_nos.contains(&series_no);
+        let pool = pool.clone();
+        let source = source.clone();
+        async move {
+            if already_present {
+                to_scrape_result(series_no, ScrapeOutcome::AlreadyPresent, None)
+            } else {
+                scrape_and_store_crossword(pool, source.as_ref(), series, series_no).await
+            }
+        }
+    }))
+    .buffered(concurrency)
+    .collect()
+    .await
+}
+
+/// Scrapes and stores a single crossword, recording how it went rather than failing
+async fn scrape_and_store_crossword(
+    pool: web::Data<DbPool>,
+    source: &dyn CrosswordSource,
+    series: &str,
+    series_no: i64,
+) -> CrosswordScrapeResult {
+    let guardian_crossword = match source.fetch_crossword(series, series_no).await {
+        Ok(guardian_crossword) => guardian_crossword,
+        Err(error @ AppError::FetchError(_)) => {
+            return to_scrape_result(series_no, ScrapeOutcome::FetchError, Some(error))
+        }
+        Err(error) => return to_scrape_result(series_no, ScrapeOutcome::ParseError, Some(error)),
+    };
+    let stored = match to_crossword(series, &guardian_crossword) {
+        Ok(new_crossword) => store_crosswords(pool, vec![new_crossword]).await,
+        Err(error) => Err(AppError::from(error)),
+    };
+    match stored {
+        Ok(_) => to_scrape_result(series_no, ScrapeOutcome::Stored, None),
+        Err(error) => to_scrape_result(series_no, ScrapeOutcome::DbError, Some(error)),
+    }
+}
+
+fn to_scrape_result(
+    series_no: i64,
+    outcome: ScrapeOutcome,
+    error: Option<AppError>,
+) -> CrosswordScrapeResult {
+    CrosswordScrapeResult {
+        series_no,
+        outcome,
+        error: error.map(|e| e.to_string()),
+    }
+}
+
+pub fn to_crossword(
+    series: &str,
+    guardian_crossword: &GuardianCrossword,
+) -> Result<Crossword, serde_json::Error> {
+    serde_json::to_value(guardian_crossword).map(|json_value| Crossword {
+        id: Uuid::new_v4().to_string(),
+        series: series.to_string(),
+        series_no: guardian_crossword.number,
+        crossword_json: json_value,
+        date: guardian_crossword.date,
     })
-    .collect();
-    let updated_crosswords = store_crosswords(pool.clone(), new_crosswords?).await?;
-    Ok(format!(
-        "Successfully scraped {} new crosswords",
-        updated_crosswords.to_string()
+}
+
+/// The crossword with every answer removed, which anyone can see
+pub async fn get_public_guardian_crossword(
+    pool: web::Data<DbPool>,
+    id: String,
+) -> Result<GuardianCrossword, AppError> {
+    Ok(without_solution(
+        get_guardian_crossword_for_id(pool, id).await?,
     ))
 }
 
+/// Prize crosswords only have their answers published some days after the crossword
+pub fn solution_released(guardian_crossword: &GuardianCrossword) -> bool {
+    guardian_crossword.solution_available
+        && guardian_crossword.date_solution_available <= Utc::now().timestamp_millis()
+}
+
+pub fn without_solution(guardian_crossword: GuardianCrossword) -> GuardianCrossword {
+    GuardianCrossword {
+        entries: guardian_crossword
+            .entries
+            .into_iter()
+            .map(|entry| GuardianEntry {
+                solution: String::new(),
+                ..entry
+            })
+            .collect(),
+        rebus_cells: guardian_crossword
+            .rebus_cells
+            .into_iter()
+            .map(|rebus_cell| RebusCell {
+                solution: String::new(),
+                ..rebus_cell
+            })
+            .collect(),
+        ..guardian_crossword
+    }
+}
+
 pub fn guardian_to_crossword_dto(guardian_crossword: GuardianCrossword) -> CrosswordDto {
     let (across, down): (Vec<GuardianEntry>, Vec<GuardianEntry>) = guardian_crossword
         .clone()
@@ -111,8 +309,13 @@ pub fn guardian_to_crossword_dto(guardian_crossword: GuardianCrossword) -> Cross
         .iter()
         .flat_map(|x| to_interim_clue(x.clone(), guardian_crossword.dimensions.cols))
         .into_group_map();
+    let circled_indexes: Vec<i64> = guardian_crossword
+        .circled_cells
+        .iter()
+        .map(|position| position.x + position.y * guardian_crossword.dimensions.cols)
+        .collect();
     let grid = (0..(guardian_crossword.dimensions.cols * guardian_crossword.dimensions.rows))
-        .map(|x| get_cell(index_to_clue_items.get(&x)))
+        .map(|x| get_cell(index_to_clue_items.get(&x), circled_indexes.contains(&x)))
         .collect();
     CrosswordDto {
         number_of_columns: guardian_crossword.dimensions.cols,
@@ -142,18 +345,18 @@ fn to_interim_clue(entry: GuardianEntry, columns: i64) -> Vec<(i64, (ClueId, Opt
     other_positions.push(first_position);
     other_positions
 }
-fn guardian_to_dto_direction(direction: GuardianDirection) -> Direction {
+pub fn guardian_to_dto_direction(direction: GuardianDirection) -> Direction {
     match direction {
         GuardianDirection::Across => Direction::Across,
         GuardianDirection::Down => Direction::Down,
     }
 }
 
-fn get_cell(clue_items: Option<&Vec<(ClueId, Option<i64>)>>) -> Cell {
-    match clue_items.clone() {
+fn get_cell(clue_items: Option<&Vec<(ClueId, Option<i64>)>>, circled: bool) -> Cell {
+    match clue_items {
         None => Black,
         Some(clues) => {
-            let first_clue = clues.get(0);
+            let first_clue = clues.first();
             let second_clue = clues.get(1);
             let number = first_clue
                 .and_then(|&(_, n)| n)
@@ -164,6 +367,7 @@ fn get_cell(clue_items: Option<&Vec<(ClueId, Option<i64>)>>) -> Cell {
                         number,
                         clue_id: clue_id.clone(),
                         clue_id_2: second_clue.map(|(other, _)| other.clone()),
+                        circled,
                     },
                 })
                 .unwrap_or(Black)
@@ -0,0 +1,26 @@
+use futures::future::LocalBoxFuture;
+
+use crate::models::errors::AppError;
+use crate::models::guardian::GuardianCrossword;
+
+/// A provider of crosswords that `update_crosswords` and `backfill_crosswords` can scrape from
+pub trait CrosswordSource: Send + Sync {
+    /// How many crosswords may be fetched from the source at once
+    fn concurrency(&self) -> usize {
+        1
+    }
+
+    /// Series numbers of the crosswords the source currently lists for a series
+    fn list_crossword_nos<'a>(
+        &'a self,
+        series: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<i64>, AppError>>;
+
+    /// Fetches a single crossword, failing with `AppError::FetchError` when it could not be
+    /// retrieved and `AppError::ParseError` when it could not be understood
+    fn fetch_crossword<'a>(
+        &'a self,
+        series: &'a str,
+        series_no: i64,
+    ) -> LocalBoxFuture<'a, Result<GuardianCrossword, AppError>>;
+}
@@ -0,0 +1,63 @@
+use actix_web::web;
+use std::collections::HashMap;
+
+use crate::formats::ipuz::to_ipuz;
+use crate::formats::puz::write_puz;
+use crate::formats::xd::to_xd;
+use crate::models::errors::AppError;
+use crate::services::crossword_db_actions::get_guardian_crossword_for_id;
+use crate::services::crossword_service::without_solution;
+use crate::services::solution_db_actions::get_solution;
+use crate::DbPool;
+
+/// Writes a crossword as ipuz, only admins can include the answers
+pub async fn export_ipuz(
+    pool: web::Data<DbPool>,
+    id: String,
+    include_solution: bool,
+    admin: bool,
+) -> Result<serde_json::Value, AppError> {
+    if include_solution && !admin {
+        return Err(AppError::Unauthorized);
+    }
+    let guardian_crossword = get_guardian_crossword_for_id(pool, id).await?;
+    Ok(to_ipuz(&guardian_crossword, include_solution))
+}
+
+/// Writes a crossword as a `.puz` file, filled in with the team's current solution if one is given
+/// but leaving out pencilled guesses. The answers are left out for non-admins
+pub async fn export_puz(
+    pool: web::Data<DbPool>,
+    id: String,
+    team: Option<String>,
+    admin: bool,
+) -> Result<Vec<u8>, AppError> {
+    let mut guardian_crossword = get_guardian_crossword_for_id(pool.clone(), id.clone()).await?;
+    if !admin {
+        guardian_crossword = without_solution(guardian_crossword);
+    }
+    let entered: HashMap<(i64, i64), String> = match team {
+        Some(team) => get_solution(pool, id, team)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| item.mark.is_pen())
+            .map(|item| ((item.x, item.y), item.value))
+            .collect(),
+        None => HashMap::new(),
+    };
+    write_puz(&guardian_crossword, &entered)
+}
+
+/// Writes a crossword as `.xd`, which is only for admins as its grid is made of the answers
+pub async fn export_xd(
+    pool: web::Data<DbPool>,
+    id: String,
+    admin: bool,
+) -> Result<String, AppError> {
+    if !admin {
+        return Err(AppError::Unauthorized);
+    }
+    let guardian_crossword = get_guardian_crossword_for_id(pool, id).await?;
+    to_xd(&guardian_crossword)
+}
@@ -0,0 +1,90 @@
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use scraper::Html;
+use std::num::ParseIntError;
+
+use crate::config::ScraperConfig;
+use crate::models::errors::AppError;
+use crate::models::guardian::GuardianCrossword;
+use crate::services::crossword_source::CrosswordSource;
+use crate::services::scrape_client::ScrapeClient;
+
+/// Scrapes crosswords from the Guardian website, or a stub of it at the configured base URL
+#[derive(Debug)]
+pub struct GuardianSource {
+    client: ScrapeClient,
+    base_url: String,
+    concurrency: usize,
+}
+
+impl GuardianSource {
+    pub fn new(config: &ScraperConfig) -> Result<GuardianSource, AppError> {
+        Ok(GuardianSource {
+            client: ScrapeClient::new(config)?,
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            concurrency: config.concurrency,
+        })
+    }
+
+    async fn scrape_crossword(
+        &self,
+        series: &str,
+        id: String,
+    ) -> Result<GuardianCrossword, AppError> {
+        let url = format!("{}/crosswords/{}/{}", self.base_url, series, id);
+        let document = self.get_document(url).await?;
+        let selector = scraper::Selector::parse(".js-crossword")?;
+        let element = document
+            .select(&selector)
+            .next_back()
+            .ok_or(AppError::ParseError("No element found".to_string()))?;
+        let json = element
+            .value()
+            .attr("data-crossword-data")
+            .ok_or(AppError::ParseError("No attribute found".to_string()))?;
+        serde_json::from_str(json).map_err(|e| AppError::ParseError(e.to_string()))
+    }
+
+    async fn get_document(&self, url: String) -> Result<Html, AppError> {
+        let response = self.client.get_text(&url).await?;
+        Ok(Html::parse_document(&response))
+    }
+
+    async fn get_recent_crossword_nos(&self, series: &str) -> Result<Vec<i64>, AppError> {
+        let url = format!("{}/crosswords/series/{}", self.base_url, series);
+        let series_path = format!("/crosswords/{}/", series);
+        let document = self.get_document(url).await?;
+        let selector = scraper::Selector::parse(".fc-item__container>a")?;
+        let crossword_nos: Result<Vec<i64>, ParseIntError> = document
+            .select(&selector)
+            .filter_map(|s| s.value().attr("href"))
+            // links are absolute on the Guardian but may be relative on a stub
+            .map(|href| href.strip_prefix(self.base_url.as_str()).unwrap_or(href))
+            .filter_map(|path| path.strip_prefix(series_path.as_str()))
+            .map(|crossword_no| crossword_no.replace('/', "").parse::<i64>())
+            .collect();
+        crossword_nos.map_err(|e| AppError::ParseError(e.to_string()))
+    }
+}
+
+impl CrosswordSource for GuardianSource {
+    fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    fn list_crossword_nos<'a>(
+        &'a self,
+        series: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Vec<i64>, AppError>> {
+        self.get_recent_crossword_nos(series).boxed_local()
+    }
+
+    fn fetch_crossword<'a>(
+        &'a self,
+        series: &'a str,
+        series_no: i64,
+    ) -> LocalBoxFuture<'a, Result<GuardianCrossword, AppError>> {
+        self.scrape_crossword(series, series_no.to_string())
+            .boxed_local()
+    }
+}
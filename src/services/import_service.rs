@@ -0,0 +1,136 @@
+use actix_web::web;
+use std::path::Path;
+
+use crate::config::{scrape_series, GUARDIAN_SERIES};
+use crate::formats::grid::to_guardian_crossword;
+use crate::formats::ipuz::parse_ipuz;
+use crate::formats::puz::parse_puz;
+use crate::formats::xd::parse_xd;
+use crate::models::api_models::{CrosswordMetadata, FailedImport, ImportFormat, ImportReport};
+use crate::models::db_models::Crossword;
+use crate::models::errors::AppError;
+use crate::models::guardian::GuardianCrossword;
+use crate::services::crossword_db_actions::store_numbered_crosswords;
+use crate::services::crossword_service::to_crossword;
+use crate::DbPool;
+
+const XD_EXTENSION: &str = "xd";
+
+/// Parses an uploaded crossword and stores it as the next number in the series
+pub async fn import_crossword(
+    pool: web::Data<DbPool>,
+    series: String,
+    format: ImportFormat,
+    data: &[u8],
+) -> Result<CrosswordMetadata, AppError> {
+    let series = importable_series(series)?;
+    let imported = match format {
+        ImportFormat::Puz => parse_puz(data)?,
+        ImportFormat::Ipuz => parse_ipuz(data)?,
+        ImportFormat::Xd => parse_xd(data)?,
+    };
+    let guardian_crossword = to_guardian_crossword(imported, &series)?;
+    store_numbered_crosswords(pool, series.clone(), move |series_no| {
+        Ok(vec![to_imported_crossword(
+            guardian_crossword,
+            &series,
+            series_no,
+        )?])
+    })
+    .await?
+    .first()
+    .map(to_metadata)
+    .ok_or_else(|| AppError::InternalServerError("Imported crossword wasn't stored".to_string()))
+}
+
+/// Imports every `.xd` file in a directory in file name order, reporting any that fail to parse
+pub async fn import_xd_directory(
+    pool: web::Data<DbPool>,
+    series: String,
+    directory: &Path,
+) -> Result<ImportReport, AppError> {
+    let series = importable_series(series)?;
+    let read_error = |e: std::io::Error| {
+        AppError::BadRequest(format!("Cannot read {}: {}", directory.display(), e))
+    };
+    let mut paths: Vec<_> = std::fs::read_dir(directory)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(read_error)?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == XD_EXTENSION)
+    });
+    paths.sort();
+
+    let mut guardian_crosswords = Vec::new();
+    let mut failed = Vec::new();
+    for path in paths {
+        let result = std::fs::read(&path)
+            .map_err(|e| AppError::BadRequest(e.to_string()))
+            .and_then(|data| parse_xd(&data))
+            .and_then(|imported| to_guardian_crossword(imported, &series));
+        match result {
+            Ok(guardian_crossword) => guardian_crosswords.push(guardian_crossword),
+            Err(error) => failed.push(FailedImport {
+                file: path.display().to_string(),
+                error: error.to_string(),
+            }),
+        }
+    }
+    let series_for = series.clone();
+    let imported = store_numbered_crosswords(pool, series.clone(), move |first_series_no| {
+        guardian_crosswords
+            .into_iter()
+            .zip(first_series_no..)
+            .map(|(guardian_crossword, series_no)| {
+                to_imported_crossword(guardian_crossword, &series_for, series_no)
+            })
+            .collect()
+    })
+    .await?
+    .iter()
+    .map(to_metadata)
+    .collect();
+    Ok(ImportReport {
+        series,
+        imported,
+        failed,
+    })
+}
+
+/// Imports go into series of their own so they can't clash with scraped numbers, even for Guardian
+/// series that aren't scraped yet
+fn importable_series(series: String) -> Result<String, AppError> {
+    let series = series.trim().to_lowercase();
+    if series.is_empty() {
+        return Err(AppError::BadRequest("A series is required".to_string()));
+    }
+    if GUARDIAN_SERIES.contains(&series.as_str()) || scrape_series().contains(&series) {
+        return Err(AppError::BadRequest(format!(
+            "Series {} is reserved for crosswords scraped from the Guardian, choose another series",
+            series
+        )));
+    }
+    Ok(series)
+}
+
+fn to_imported_crossword(
+    mut guardian_crossword: GuardianCrossword,
+    series: &str,
+    series_no: i64,
+) -> Result<Crossword, AppError> {
+    guardian_crossword.number = series_no;
+    guardian_crossword.id = format!("imported/{}/{}", series, series_no);
+    Ok(to_crossword(series, &guardian_crossword)?)
+}
+
+fn to_metadata(crossword: &Crossword) -> CrosswordMetadata {
+    CrosswordMetadata {
+        id: crossword.id.clone(),
+        series: crossword.series.clone(),
+        series_no: crossword.series_no,
+        date: crossword.date,
+    }
+}
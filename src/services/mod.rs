@@ -1,6 +1,24 @@
+pub mod auth_service;
+pub mod chat_db_actions;
+pub mod chat_service;
+pub mod check_service;
+pub mod clue_note_db_actions;
+pub mod clue_note_service;
+pub mod contribution_service;
 pub mod crossword_db_actions;
 pub mod crossword_service;
+pub mod crossword_source;
+pub mod export_service;
+pub mod guardian_source;
+pub mod import_service;
+pub mod replay_service;
+pub mod scrape_client;
+pub mod scrape_run_db_actions;
+pub mod scrape_run_service;
+pub mod scrape_scheduler;
 pub mod solution_db_actions;
 pub mod solution_service;
+pub mod solve_progress_db_actions;
+pub mod solve_progress_service;
 pub mod ws_server;
 pub mod ws_session;
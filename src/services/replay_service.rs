@@ -0,0 +1,86 @@
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use actix_web::web;
+
+use crate::models::api_models::{MarkType, Replay, ReplayMove, SolutionItemDto};
+use crate::models::errors::AppError;
+use crate::services::solution_db_actions::get_move_events;
+use crate::DbPool;
+
+/// Longest pause between moves in a replay before speeding up, so breaks don't replay in full
+const MAX_REPLAY_GAP_MILLIS: i64 = 5_000;
+
+/// Speeds a replay can be played at, bounded so frame offsets always fit in a `Duration`
+const REPLAY_SPEEDS: RangeInclusive<f64> = 0.1..=100.0;
+
+pub async fn get_replay(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Replay, AppError> {
+    let moves = get_move_events(pool, crossword_id.clone(), team_id.clone())
+        .await?
+        .into_iter()
+        .map(|event| ReplayMove {
+            seq: event.seq,
+            user: event.user_for,
+            x: event.x,
+            y: event.y,
+            value: event.value,
+            revealed: event.revealed,
+            mark: MarkType::from(event.mark.as_str()),
+            candidates: event.candidates,
+            at: event.created_at,
+        })
+        .collect();
+    Ok(Replay {
+        team: team_id,
+        crossword_id,
+        moves,
+    })
+}
+
+/// Groups moves made together into frames, each with how long after the start of the replay it
+/// should be sent at the given speed
+pub fn replay_frames(
+    moves: &[ReplayMove],
+    speed: f64,
+) -> Result<Vec<(Duration, Vec<SolutionItemDto>)>, AppError> {
+    if !REPLAY_SPEEDS.contains(&speed) {
+        return Err(AppError::BadRequest(format!(
+            "Replay speed must be from {} to {}, got {}",
+            REPLAY_SPEEDS.start(),
+            REPLAY_SPEEDS.end(),
+            speed
+        )));
+    }
+    let mut frames: Vec<(Duration, Vec<SolutionItemDto>)> = Vec::new();
+    let mut elapsed_millis = 0;
+    let mut previous_at: Option<i64> = None;
+    for replay_move in moves {
+        let solution_item = SolutionItemDto {
+            x: replay_move.x,
+            y: replay_move.y,
+            value: replay_move.value.clone(),
+            revealed: replay_move.revealed,
+            version: Some(replay_move.seq),
+            mark: replay_move.mark,
+            candidates: replay_move.candidates.clone(),
+        };
+        match previous_at {
+            Some(at) if at == replay_move.at => {
+                if let Some((_, solution_items)) = frames.last_mut() {
+                    solution_items.push(solution_item);
+                }
+                continue;
+            }
+            Some(at) => elapsed_millis += (replay_move.at - at).clamp(0, MAX_REPLAY_GAP_MILLIS),
+            None => (),
+        }
+        previous_at = Some(replay_move.at);
+        let offset = Duration::from_secs_f64(elapsed_millis as f64 / 1000.0 / speed);
+        frames.push((offset, vec![solution_item]));
+    }
+    Ok(frames)
+}
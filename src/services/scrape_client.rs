@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::rt::time::sleep;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Proxy, Response, StatusCode, Url};
+
+use crate::config::ScraperConfig;
+use crate::models::errors::AppError;
+
+/// HTTP client for scraping that limits the request rate per host and retries rate limited,
+/// failed and timed out requests with exponential backoff
+#[derive(Debug)]
+pub struct ScrapeClient {
+    client: Client,
+    min_interval: Duration,
+    max_retries: u32,
+    backoff_base: Duration,
+    backoff_max: Duration,
+    next_request_at: Mutex<HashMap<String, Instant>>,
+}
+
+enum Attempt {
+    Done(Result<String, AppError>),
+    Retry(Duration, AppError),
+}
+
+impl ScrapeClient {
+    pub fn new(config: &ScraperConfig) -> Result<ScrapeClient, AppError> {
+        let mut builder = Client::builder()
+            .user_agent(config.user_agent.clone())
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout);
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(Proxy::all(proxy).map_err(invalid_config)?);
+        }
+        let min_interval =
+            Duration::try_from_secs_f64(1.0 / config.requests_per_second).map_err(|e| {
+                AppError::InternalServerError(format!(
+                    "Invalid scraper config: requests per second {}: {}",
+                    config.requests_per_second, e
+                ))
+            })?;
+        Ok(ScrapeClient {
+            client: builder.build().map_err(invalid_config)?,
+            min_interval,
+            max_retries: config.max_retries,
+            backoff_base: config.backoff_base,
+            backoff_max: config.backoff_max,
+            next_request_at: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn get_text(&self, url: &str) -> Result<String, AppError> {
+        let parsed_url = Url::parse(url).map_err(|e| AppError::FetchError(e.to_string()))?;
+        let host = parsed_url.host_str().unwrap_or_default().to_string();
+        let mut attempt = 0;
+        loop {
+            self.wait_for_turn(&host).await;
+            match self.attempt(&host, parsed_url.clone(), attempt).await {
+                Attempt::Done(result) => return result,
+                Attempt::Retry(_, error) if attempt >= self.max_retries => return Err(error),
+                Attempt::Retry(delay, error) => {
+                    println!("Retrying {} in {:?}: {}", url, delay, error);
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn attempt(&self, host: &str, url: Url, attempt: u32) -> Attempt {
+        let response = match self.client.get(url).send().await {
+            Ok(response) => response,
+            Err(error) if error.is_timeout() || error.is_connect() => {
+                return Attempt::Retry(self.backoff(attempt), error.into())
+            }
+            Err(error) => return Attempt::Done(Err(error.into())),
+        };
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            let error = AppError::FetchError(format!("{} returned {}", response.url(), status));
+            return match retry_after(&response) {
+                Some(delay) if delay > self.backoff_max => Attempt::Done(Err(error)),
+                Some(delay) => {
+                    self.hold_back(host, delay);
+                    Attempt::Retry(delay, error)
+                }
+                None => Attempt::Retry(self.backoff(attempt), error),
+            };
+        }
+        let response = match response.error_for_status() {
+            Ok(response) => response,
+            Err(error) => return Attempt::Done(Err(error.into())),
+        };
+        match response.text().await {
+            Ok(text) => Attempt::Done(Ok(text)),
+            // the timeout covers reading the body too, which can stall after the headers arrive
+            Err(error) if error.is_timeout() => Attempt::Retry(self.backoff(attempt), error.into()),
+            Err(error) => Attempt::Done(Err(error.into())),
+        }
+    }
+
+    /// Waits until the host can be sent another request without exceeding the rate limit
+    async fn wait_for_turn(&self, host: &str) {
+        let wait = {
+            let mut next_request_at = match self.next_request_at.lock() {
+                Ok(next_request_at) => next_request_at,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let now = Instant::now();
+            let turn = next_request_at
+                .get(host)
+                .copied()
+                .filter(|next| *next > now)
+                .unwrap_or(now);
+            next_request_at.insert(host.to_string(), turn + self.min_interval);
+            turn - now
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// Stops any request being sent to the host until the delay has passed
+    fn hold_back(&self, host: &str, delay: Duration) {
+        let mut next_request_at = match self.next_request_at.lock() {
+            Ok(next_request_at) => next_request_at,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let until = Instant::now() + delay;
+        let next = next_request_at.entry(host.to_string()).or_insert(until);
+        *next = (*next).max(until);
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .backoff_base
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.backoff_max);
+        let jitter_millis = (exponential.as_millis() as u64 / 4).max(1);
+        exponential + Duration::from_millis(rand::thread_rng().gen_range(0..jitter_millis))
+    }
+}
+
+/// Reads a `Retry-After` header given either in seconds or as an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let retry_at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (retry_at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+fn invalid_config(error: reqwest::Error) -> AppError {
+    AppError::InternalServerError(format!("Invalid scraper config: {}", error))
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use actix_web::rt::task::JoinHandle;
+use actix_web::rt::time::interval;
+use actix_web::web;
+use chrono::Utc;
+use diesel::sql_types::{BigInt, Text};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::models::db_models::ScrapeRun;
+use crate::models::errors::AppError;
+use crate::schema::scrape_lease;
+use crate::schema::scrape_run::dsl::{id, scrape_run, started_at};
+use crate::DbPool;
+
+/// How long a lease on a series lasts without being renewed
+const LEASE_MILLIS: i64 = 2 * 60 * 1000;
+/// How often a held lease is renewed, well within `LEASE_MILLIS`
+const LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A lease on scraping a series, stored in `scrape_lease` so it is shared by every server. It is
+/// renewed while held, and left to lapse if dropped without being released
+pub struct SeriesLease {
+    series: String,
+    holder: String,
+    renewal: JoinHandle<()>,
+}
+
+impl Drop for SeriesLease {
+    fn drop(&mut self) {
+        self.renewal.abort();
+    }
+}
+
+/// Takes the lease on scraping the series, giving `None` when another scrape holds it
+pub async fn try_lease_series(
+    pool: web::Data<DbPool>,
+    series: String,
+) -> actix_web::Result<Option<SeriesLease>, AppError> {
+    let holder = Uuid::new_v4().to_string();
+    let (series_for, holder_for) = (series.clone(), holder.clone());
+    let block_pool = pool.clone();
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let leased = web::block(move || {
+        let mut conn = block_pool.get()?;
+        let now = Utc::now().timestamp_millis();
+        diesel::sql_query(
+            "INSERT INTO scrape_lease (series, holder, expires_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (series) DO UPDATE \
+             SET holder = excluded.holder, expires_at = excluded.expires_at \
+             WHERE scrape_lease.expires_at < $4",
+        )
+        .bind::<Text, _>(series_for)
+        .bind::<Text, _>(holder_for)
+        .bind::<BigInt, _>(now + LEASE_MILLIS)
+        .bind::<BigInt, _>(now)
+        .execute(&mut conn)
+        .map(|updated| updated > 0)
+        .map_err(AppError::from)
+    })
+    .await??;
+    if !leased {
+        return Ok(None);
+    }
+    let renewal = actix_web::rt::spawn(renew_lease(pool, series.clone(), holder.clone()));
+    Ok(Some(SeriesLease {
+        series,
+        holder,
+        renewal,
+    }))
+}
+
+async fn renew_lease(pool: web::Data<DbPool>, series: String, holder: String) {
+    let mut renew_at = interval(LEASE_RENEW_INTERVAL);
+    renew_at.tick().await;
+    loop {
+        renew_at.tick().await;
+        let (pool, series_for, holder_for) = (pool.clone(), series.clone(), holder.clone());
+        // use web::block to offload blocking Diesel queries without blocking server thread
+        let result = web::block(move || {
+            let mut conn = pool.get()?;
+            diesel::update(scrape_lease::table.find(series_for))
+                .filter(scrape_lease::holder.eq(holder_for))
+                .set(scrape_lease::expires_at.eq(Utc::now().timestamp_millis() + LEASE_MILLIS))
+                .execute(&mut conn)
+                .map_err(AppError::from)
+        })
+        .await;
+        match result {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => println!("Failed to renew lease on {}: {}", series, e),
+            Err(e) => println!("Failed to renew lease on {}: {}", series, e),
+        }
+    }
+}
+
+pub async fn release_series_lease(
+    pool: web::Data<DbPool>,
+    lease: SeriesLease,
+) -> actix_web::Result<usize, AppError> {
+    lease.renewal.abort();
+    let (series_for, holder_for) = (lease.series.clone(), lease.holder.clone());
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        diesel::delete(scrape_lease::table.find(series_for))
+            .filter(scrape_lease::holder.eq(holder_for))
+            .execute(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await?
+}
+
+pub async fn store_or_update_scrape_run(
+    pool: web::Data<DbPool>,
+    run: ScrapeRun,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        diesel::insert_into(scrape_run)
+            .values(&run)
+            .on_conflict(id)
+            .do_update()
+            .set(&run)
+            .execute(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn get_scrape_runs(
+    pool: web::Data<DbPool>,
+    offset: i64,
+    limit: i64,
+) -> actix_web::Result<(Vec<ScrapeRun>, i64), AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        let runs = scrape_run
+            .order(started_at.desc())
+            .offset(offset)
+            .limit(limit)
+            .load(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+        let total = scrape_run
+            .count()
+            .get_result(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+        Ok((runs, total))
+    })
+    .await?
+}
@@ -0,0 +1,98 @@
+use actix_web::web;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::models::api_models::{
+    CrosswordScrapeResult, PageQuery, ScrapeOutcome, ScrapeRunDto, ScrapeRunPage, ScrapeTrigger,
+};
+use crate::models::db_models::ScrapeRun;
+use crate::models::errors::AppError;
+use crate::services::scrape_run_db_actions::{get_scrape_runs, store_or_update_scrape_run};
+use crate::DbPool;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+pub fn new_scrape_run(series: &str, trigger: ScrapeTrigger) -> ScrapeRun {
+    ScrapeRun {
+        id: Uuid::new_v4().to_string(),
+        series: series.to_string(),
+        trigger: trigger.to_string(),
+        started_at: Utc::now().timestamp_millis(),
+        finished_at: None,
+        stored: 0,
+        already_present: 0,
+        failed: 0,
+        error: None,
+        errors_json: serde_json::Value::Array(Vec::new()),
+    }
+}
+
+pub fn finish_scrape_run(
+    run: ScrapeRun,
+    error: Option<String>,
+    results: &[CrosswordScrapeResult],
+) -> ScrapeRun {
+    let count = |outcome: ScrapeOutcome| {
+        results
+            .iter()
+            .filter(|result| result.outcome == outcome)
+            .count() as i64
+    };
+    let failed: Vec<&CrosswordScrapeResult> = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .collect();
+    ScrapeRun {
+        finished_at: Some(Utc::now().timestamp_millis()),
+        stored: count(ScrapeOutcome::Stored),
+        already_present: count(ScrapeOutcome::AlreadyPresent),
+        failed: failed.len() as i64,
+        error,
+        errors_json: serde_json::to_value(failed).unwrap_or(serde_json::Value::Array(Vec::new())),
+        ..run
+    }
+}
+
+/// Records a run, a failure to record is logged rather than failing the scrape itself
+pub async fn record_scrape_run(pool: web::Data<DbPool>, run: ScrapeRun) {
+    let run_id = run.id.clone();
+    if let Err(e) = store_or_update_scrape_run(pool, run).await {
+        println!("Could not record scrape run {}: {}", run_id, e);
+    }
+}
+
+pub async fn get_scrape_run_page(
+    pool: web::Data<DbPool>,
+    query: PageQuery,
+) -> Result<ScrapeRunPage, AppError> {
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let (runs, total) = get_scrape_runs(pool, (page - 1) * page_size, page_size).await?;
+    let runs: Result<Vec<ScrapeRunDto>, serde_json::Error> = runs
+        .into_iter()
+        .map(|run| {
+            serde_json::from_value(run.errors_json).map(|errors| ScrapeRunDto {
+                id: run.id,
+                series: run.series,
+                trigger: run.trigger,
+                started_at: run.started_at,
+                finished_at: run.finished_at,
+                stored: run.stored,
+                already_present: run.already_present,
+                failed: run.failed,
+                error: run.error,
+                errors,
+            })
+        })
+        .collect();
+    Ok(ScrapeRunPage {
+        page,
+        page_size,
+        total,
+        runs: runs?,
+    })
+}
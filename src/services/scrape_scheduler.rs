@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::prelude::*;
+use actix_web::web::Data;
+use chrono::{DateTime, Datelike, Days, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use rand::Rng;
+
+use crate::models::api_models::{ScheduleStatus, ScrapeTrigger};
+use crate::services::crossword_service::update_crosswords;
+use crate::services::crossword_source::CrosswordSource;
+use crate::DbPool;
+
+const ALL_DAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// When to scrape a series, parsed from eg. `cryptic=Mon-Fri@00:05` or `prize=Sat,Sun@09:30`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrapeSchedule {
+    pub series: String,
+    pub days: Vec<Weekday>,
+    pub time: NaiveTime,
+}
+
+impl ScrapeSchedule {
+    fn next_run_after(&self, now: DateTime<Utc>, timezone: Tz) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&timezone).date_naive();
+        (0..=7)
+            .filter_map(|offset| today.checked_add_days(Days::new(offset)))
+            .filter(|date| self.days.contains(&date.weekday()))
+            .filter_map(|date| {
+                timezone
+                    .from_local_datetime(&date.and_time(self.time))
+                    .earliest()
+            })
+            .map(|run_at| run_at.with_timezone(&Utc))
+            .find(|run_at| *run_at > now)
+    }
+}
+
+impl fmt::Display for ScrapeSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days: Vec<String> = self.days.iter().map(|day| day.to_string()).collect();
+        write!(
+            f,
+            "{}={}@{}",
+            self.series,
+            days.join(","),
+            self.time.format("%H:%M")
+        )
+    }
+}
+
+impl FromStr for ScrapeSchedule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid scrape schedule: {}", value);
+        let (series, when) = value.trim().split_once('=').ok_or_else(invalid)?;
+        let (days, time) = when.split_once('@').ok_or_else(invalid)?;
+        let time = NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid())?;
+        let days = parse_days(days.trim()).ok_or_else(invalid)?;
+        if series.trim().is_empty() || days.is_empty() {
+            return Err(invalid());
+        }
+        Ok(ScrapeSchedule {
+            series: series.trim().to_lowercase(),
+            days,
+            time,
+        })
+    }
+}
+
+/// Parses each schedule, failing on the first invalid one
+pub fn parse_schedules(schedules: Vec<String>) -> Result<Vec<ScrapeSchedule>, String> {
+    schedules.iter().map(|schedule| schedule.parse()).collect()
+}
+
+fn parse_days(days: &str) -> Option<Vec<Weekday>> {
+    if days == "*" {
+        return Some(ALL_DAYS.to_vec());
+    }
+    let mut result: Vec<Weekday> = Vec::new();
+    for part in days.split(',') {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start = ALL_DAYS.iter().position(|d| Ok(*d) == start.parse())?;
+                let end = ALL_DAYS.iter().position(|d| Ok(*d) == end.parse())?;
+                if start > end {
+                    return None;
+                }
+                result.extend_from_slice(&ALL_DAYS[start..=end]);
+            }
+            None => result.push(part.trim().parse().ok()?),
+        }
+    }
+    Some(result)
+}
+
+/// Returns the status of every schedule
+#[derive(Message)]
+#[rtype(result = "Vec<ScheduleStatus>")]
+pub struct GetScheduleStatus;
+
+/// Runs `update_crosswords` for each configured series on its schedule
+pub struct ScrapeScheduler {
+    pool: DbPool,
+    source: Arc<dyn CrosswordSource>,
+    schedules: Vec<ScrapeSchedule>,
+    timezone: Tz,
+    jitter: Duration,
+    statuses: HashMap<String, ScheduleStatus>,
+}
+
+impl ScrapeScheduler {
+    pub fn new(
+        pool: DbPool,
+        source: Arc<dyn CrosswordSource>,
+        schedules: Vec<ScrapeSchedule>,
+        timezone: Tz,
+        jitter: Duration,
+    ) -> ScrapeScheduler {
+        let statuses = schedules
+            .iter()
+            .map(|schedule| {
+                (
+                    schedule.to_string(),
+                    ScheduleStatus {
+                        series: schedule.series.clone(),
+                        schedule: schedule.to_string(),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        ScrapeScheduler {
+            pool,
+            source,
+            schedules,
+            timezone,
+            jitter,
+            statuses,
+        }
+    }
+
+    fn schedule_next_run(&mut self, schedule: ScrapeSchedule, ctx: &mut Context<Self>) {
+        let now = Utc::now();
+        let next_run = match schedule.next_run_after(now, self.timezone) {
+            Some(next_run) => next_run,
+            None => {
+                println!("Could not find next run for schedule {}", schedule);
+                return;
+            }
+        };
+        let jitter_millis = self.jitter.as_millis() as i64;
+        let jitter = if jitter_millis == 0 {
+            0
+        } else {
+            rand::thread_rng().gen_range(0..jitter_millis)
+        };
+        let run_at = next_run + chrono::Duration::milliseconds(jitter);
+        let delay = (run_at - now).to_std().unwrap_or(Duration::ZERO);
+        if let Some(status) = self.statuses.get_mut(&schedule.to_string()) {
+            status.next_run_at = Some(run_at.timestamp_millis());
+        }
+        ctx.run_later(delay, move |act, ctx| {
+            act.run(schedule.clone(), ctx);
+            act.schedule_next_run(schedule, ctx);
+        });
+    }
+
+    fn run(&mut self, schedule: ScrapeSchedule, ctx: &mut Context<Self>) {
+        let key = schedule.to_string();
+        let series_running = self
+            .statuses
+            .values()
+            .any(|status| status.series == schedule.series && status.running);
+        let status = match self.statuses.get_mut(&key) {
+            Some(status) => status,
+            None => return,
+        };
+        if series_running {
+            println!("Skipping scheduled scrape of {}, already running", key);
+            status.skipped_runs += 1;
+            return;
+        }
+        status.running = true;
+        status.last_run_started_at = Some(Utc::now().timestamp_millis());
+        let pool = Data::new(self.pool.clone());
+        update_crosswords(
+            pool,
+            self.source.clone(),
+            vec![schedule.series.clone()],
+            ScrapeTrigger::Scheduled,
+        )
+        .into_actor(self)
+        .map(move |result, act, _| {
+            if let Some(status) = act.statuses.get_mut(&key) {
+                status.running = false;
+                status.last_run_finished_at = Some(Utc::now().timestamp_millis());
+                match result {
+                    Ok(report) => {
+                        let failure = report.failure();
+                        status.last_run_succeeded = Some(failure.is_none());
+                        status.last_run_error = failure;
+                        status.last_run_report = Some(report);
+                    }
+                    Err(e) => {
+                        status.last_run_succeeded = Some(false);
+                        status.last_run_error = Some(e.to_string());
+                        status.last_run_report = None;
+                    }
+                }
+            }
+        })
+        .spawn(ctx);
+    }
+}
+
+impl Actor for ScrapeScheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        for schedule in self.schedules.clone() {
+            println!("Scheduling scrape {}", schedule);
+            self.schedule_next_run(schedule, ctx);
+        }
+    }
+}
+
+impl Handler<GetScheduleStatus> for ScrapeScheduler {
+    type Result = MessageResult<GetScheduleStatus>;
+
+    fn handle(&mut self, _: GetScheduleStatus, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.schedules
+                .iter()
+                .filter_map(|schedule| self.statuses.get(&schedule.to_string()).cloned())
+                .collect(),
+        )
+    }
+}
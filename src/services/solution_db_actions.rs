@@ -1,63 +1,386 @@
 #![allow(unused)]
 
+use std::collections::{BTreeMap, HashMap};
+
 use actix_web::web;
-use diesel::row::NamedRow;
-use diesel::OptionalExtension;
-use diesel::{ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use chrono::Utc;
+use diesel::sql_types::Text;
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, QueryResult, RunQueryDsl,
+};
+use diesel::{Connection, OptionalExtension, PgConnection};
 use serde_json::Value;
 
-use crate::models::api_models::CrosswordMetadata;
-use crate::models::db_models::{Crossword, Solution, SolutionItem};
+use crate::models::api_models::MarkType;
+use crate::models::db_models::{MoveEvent, Solution, SolutionItem};
 use crate::models::errors::AppError;
-use crate::models::errors::AppError::InternalServerError;
+use crate::schema::move_event;
 use crate::schema::solution::dsl::solution;
-use crate::schema::solution::{crossword_for, solution_json, team_for};
+use crate::schema::solution::{crossword_for, snapshot_seq, solution_json, team_for};
 use crate::DbPool;
 
+/// Gives the team's current solution, made from the latest snapshot and the moves since
 pub async fn get_solution(
     pool: web::Data<DbPool>,
     crossword_id: String,
     team_id: String,
 ) -> actix_web::Result<Option<Vec<SolutionItem>>, AppError> {
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let result: Option<Value> = web::block(move || {
+    web::block(move || {
         let mut conn = pool.get()?;
-        solution
-            .filter(crossword_for.eq(crossword_id))
-            .filter(team_for.eq(team_id))
-            .select(solution_json)
-            .first(&mut conn)
-            .optional()
-            .map_err(|x| AppError::InternalServerError(x.to_string()))
-    })
-    .await??;
-    result.map_or(Ok(None), |x| {
-        serde_json::from_value(x).map_err(|e| AppError::InternalServerError(e.to_string()))
+        load_solution(&mut conn, &crossword_id, &team_id)
+            .map(|loaded| loaded.map(|(solution_items, _)| solution_items))
     })
+    .await?
 }
 
-pub async fn store_or_update_solution(
+/// Appends the moves `choose_moves` picks given the team's current solution as one group. The
+/// solution is read under the same lock as the write, so no one else's move can land in between.
+/// Gives the stored moves along with whatever else `choose_moves` returned
+pub async fn append_checked_move_events<T, F>(
     pool: web::Data<DbPool>,
     crossword_id: String,
     team_id: String,
+    actor: String,
+    choose_moves: F,
+) -> actix_web::Result<(Vec<MoveEvent>, T), AppError>
+where
+    T: Send + 'static,
+    F: FnOnce(&HashMap<(i64, i64), SolutionItem>) -> (Vec<SolutionItem>, T) + Send + 'static,
+{
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction::<_, AppError, _>(|conn| {
+            lock_solution(conn, &crossword_id, &team_id)?;
+            let position_to_item = load_position_to_item(conn, &crossword_id, &team_id)?;
+            let (solution_items, chosen) = choose_moves(&position_to_item);
+            if solution_items.is_empty() {
+                return Ok((Vec::new(), chosen));
+            }
+            let events =
+                insert_move_events(conn, &crossword_id, &team_id, &actor, None, solution_items)?;
+            Ok((events, chosen))
+        })
+    })
+    .await?
+}
+
+/// Numbers the moves after the team's last one and stores them, creating the team's solution
+/// first if this is its first move. Callers must hold the team's lock
+fn insert_move_events(
+    conn: &mut PgConnection,
+    crossword_id: &str,
+    team_id: &str,
+    actor: &str,
+    reverts: Option<i64>,
     solution_items: Vec<SolutionItem>,
-) -> actix_web::Result<usize, AppError> {
-    // use web::block to offload blocking Diesel queries without blocking server thread1
+) -> Result<Vec<MoveEvent>, AppError> {
+    diesel::insert_into(solution)
+        .values(&Solution {
+            crossword_for: crossword_id.to_string(),
+            team_for: team_id.to_string(),
+            solution_json: serde_json::to_value(Vec::<SolutionItem>::new())?,
+            snapshot_seq: 0,
+        })
+        .on_conflict((team_for, crossword_for))
+        .do_nothing()
+        .execute(conn)?;
+    let last_seq: Option<i64> = move_event::table
+        .filter(move_event::crossword_for.eq(crossword_id))
+        .filter(move_event::team_for.eq(team_id))
+        .select(diesel::dsl::max(move_event::seq))
+        .first(conn)?;
+    let now = Utc::now().timestamp_millis();
+    let group_seq = last_seq.unwrap_or(0) + 1;
+    let events: Vec<MoveEvent> = solution_items
+        .into_iter()
+        .zip(group_seq..)
+        .map(|(item, seq)| MoveEvent {
+            crossword_for: crossword_id.to_string(),
+            team_for: team_id.to_string(),
+            seq,
+            user_for: item.modified_by,
+            x: item.x,
+            y: item.y,
+            value: item.value,
+            revealed: item.revealed,
+            created_at: now,
+            group_seq,
+            actor: actor.to_string(),
+            reverts,
+            mark: item.mark.to_string(),
+            candidates: item.candidates,
+        })
+        .collect();
+    diesel::insert_into(move_event::table)
+        .values(&events)
+        .execute(conn)?;
+    Ok(events)
+}
+
+/// Gives every move the team has made on the crossword in order
+pub async fn get_move_events(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+) -> actix_web::Result<Vec<MoveEvent>, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
     web::block(move || {
         let mut conn = pool.get()?;
-        let solution_json_to_insert = serde_json::to_value(solution_items)?;
-        let solution_to_insert = Solution {
-            team_for: team_id,
-            crossword_for: crossword_id,
-            solution_json: solution_json_to_insert.clone(),
-        };
-        diesel::insert_into(solution)
-            .values(&solution_to_insert)
-            .on_conflict((team_for, crossword_for))
-            .do_update()
-            .set((solution_json.eq(solution_json_to_insert.clone())))
-            .execute(&mut conn)
+        move_event::table
+            .filter(move_event::crossword_for.eq(crossword_id))
+            .filter(move_event::team_for.eq(team_id))
+            .order(move_event::seq.asc())
+            .load(&mut conn)
             .map_err(|e| AppError::InternalServerError(e.to_string()))
     })
     .await?
 }
+
+/// Gives the first move of each group the user made, or undid or redid, in order
+/// Reverts the first of the actor's groups, latest first, that `revert_group` gives moves for. The
+/// groups are chosen from the first move of each of the actor's groups, and the cells checked, under
+/// the same lock as the write, so a move made in between can't be overwritten. Gives the stored
+/// moves, or `None` when no group had anything to revert
+pub async fn append_revert_events<G, F>(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+    actor: String,
+    choose_groups: G,
+    revert_group: F,
+) -> actix_web::Result<Option<Vec<MoveEvent>>, AppError>
+where
+    G: FnOnce(&[MoveEvent]) -> Vec<i64> + Send + 'static,
+    F: Fn(&[MoveEvent], &[MoveEvent], &HashMap<(i64, i64), SolutionItem>) -> Vec<SolutionItem>
+        + Send
+        + 'static,
+{
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction::<_, AppError, _>(|conn| {
+            lock_solution(conn, &crossword_id, &team_id)?;
+            let position_to_item = load_position_to_item(conn, &crossword_id, &team_id)?;
+            let group_heads = load_move_group_heads(conn, &crossword_id, &team_id, &actor)?;
+            for group_seq in choose_groups(&group_heads) {
+                let (group, previous) = load_move_group(conn, &crossword_id, &team_id, group_seq)?;
+                let solution_items = revert_group(&group, &previous, &position_to_item);
+                if solution_items.is_empty() {
+                    continue;
+                }
+                let events = insert_move_events(
+                    conn,
+                    &crossword_id,
+                    &team_id,
+                    &actor,
+                    Some(group_seq),
+                    solution_items,
+                )?;
+                return Ok(Some(events));
+            }
+            Ok(None)
+        })
+    })
+    .await?
+}
+
+/// Gives the first move of each of the actor's groups, oldest first
+fn load_move_group_heads(
+    conn: &mut PgConnection,
+    crossword_id: &str,
+    team_id: &str,
+    actor: &str,
+) -> QueryResult<Vec<MoveEvent>> {
+    move_event::table
+        .filter(move_event::crossword_for.eq(crossword_id))
+        .filter(move_event::team_for.eq(team_id))
+        .filter(move_event::actor.eq(actor))
+        .filter(move_event::seq.eq(move_event::group_seq))
+        .order(move_event::group_seq.asc())
+        .load(conn)
+}
+
+/// Gives the moves of the group, along with the last move before the group in each of its cells
+fn load_move_group(
+    conn: &mut PgConnection,
+    crossword_id: &str,
+    team_id: &str,
+    group_seq: i64,
+) -> QueryResult<(Vec<MoveEvent>, Vec<MoveEvent>)> {
+    let group: Vec<MoveEvent> = move_event::table
+        .filter(move_event::crossword_for.eq(crossword_id))
+        .filter(move_event::team_for.eq(team_id))
+        .filter(move_event::group_seq.eq(group_seq))
+        .order(move_event::seq.asc())
+        .load(conn)?;
+    let mut previous: Vec<MoveEvent> = Vec::new();
+    for event in &group {
+        let last_before: Option<MoveEvent> = move_event::table
+            .filter(move_event::crossword_for.eq(crossword_id))
+            .filter(move_event::team_for.eq(team_id))
+            .filter(move_event::x.eq(event.x))
+            .filter(move_event::y.eq(event.y))
+            .filter(move_event::seq.lt(group_seq))
+            .order(move_event::seq.desc())
+            .first(conn)
+            .optional()?;
+        previous.extend(last_before);
+    }
+    Ok((group, previous))
+}
+
+/// Folds the moves since the last snapshot into a new one, returning the last move it includes
+pub async fn snapshot_solution(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+) -> actix_web::Result<i64, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction::<_, AppError, _>(|conn| {
+            lock_solution(conn, &crossword_id, &team_id)?;
+            let Some((solution_items, last_seq)) = load_solution(conn, &crossword_id, &team_id)?
+            else {
+                return Ok(0);
+            };
+            diesel::update(solution.find((crossword_id, team_id)))
+                .set((
+                    solution_json.eq(serde_json::to_value(solution_items)?),
+                    snapshot_seq.eq(last_seq),
+                ))
+                .execute(conn)?;
+            Ok(last_seq)
+        })
+    })
+    .await?
+}
+
+/// Gives each of the team's solutions, replaying only the moves after each snapshot. Reads in one
+/// repeatable read transaction so a snapshot taken meanwhile can't drop moves
+pub async fn get_solutions_for_team(
+    pool: web::Data<DbPool>,
+    team_id: String,
+) -> actix_web::Result<Vec<(String, Vec<SolutionItem>)>, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.build_transaction()
+            .repeatable_read()
+            .read_only()
+            .run::<_, AppError, _>(|conn| {
+                let snapshots: Vec<(String, Value)> = solution
+                    .filter(team_for.eq(&team_id))
+                    .select((crossword_for, solution_json))
+                    .load(conn)?;
+                let mut events_by_crossword: HashMap<String, Vec<MoveEvent>> = HashMap::new();
+                for event in move_event::table
+                    .inner_join(
+                        solution.on(crossword_for
+                            .eq(move_event::crossword_for)
+                            .and(team_for.eq(move_event::team_for))),
+                    )
+                    .filter(move_event::team_for.eq(&team_id))
+                    .filter(move_event::seq.gt(snapshot_seq))
+                    .select(move_event::all_columns)
+                    .order(move_event::seq.asc())
+                    .load::<MoveEvent>(conn)?
+                {
+                    events_by_crossword
+                        .entry(event.crossword_for.clone())
+                        .or_default()
+                        .push(event);
+                }
+                snapshots
+                    .into_iter()
+                    .map(|(crossword_id, json)| {
+                        let events = events_by_crossword
+                            .remove(&crossword_id)
+                            .unwrap_or_default();
+                        let solution_items =
+                            apply_move_events(serde_json::from_value(json)?, events);
+                        Ok((crossword_id, solution_items))
+                    })
+                    .collect()
+            })
+    })
+    .await?
+}
+
+/// Loads the latest snapshot and replays the moves after it, giving the solution along with the
+/// last move included
+fn load_solution(
+    conn: &mut PgConnection,
+    crossword_id: &str,
+    team_id: &str,
+) -> Result<Option<(Vec<SolutionItem>, i64)>, AppError> {
+    let snapshot: Option<(Value, i64)> = solution
+        .filter(crossword_for.eq(crossword_id))
+        .filter(team_for.eq(team_id))
+        .select((solution_json, snapshot_seq))
+        .first(conn)
+        .optional()?;
+    let Some((json, last_snapshot_seq)) = snapshot else {
+        return Ok(None);
+    };
+    let events: Vec<MoveEvent> = move_event::table
+        .filter(move_event::crossword_for.eq(crossword_id))
+        .filter(move_event::team_for.eq(team_id))
+        .filter(move_event::seq.gt(last_snapshot_seq))
+        .order(move_event::seq.asc())
+        .load(conn)?;
+    let last_seq = events.last().map_or(last_snapshot_seq, |event| event.seq);
+    Ok(Some((
+        apply_move_events(serde_json::from_value(json)?, events),
+        last_seq,
+    )))
+}
+
+/// Gives the team's current solution keyed by the position of each cell
+fn load_position_to_item(
+    conn: &mut PgConnection,
+    crossword_id: &str,
+    team_id: &str,
+) -> Result<HashMap<(i64, i64), SolutionItem>, AppError> {
+    Ok(load_solution(conn, crossword_id, team_id)?
+        .map_or(Vec::new(), |(solution_items, _)| solution_items)
+        .into_iter()
+        .map(|item| ((item.x, item.y), item))
+        .collect())
+}
+
+/// Applies moves in order over a solution, the latest move in each cell winning
+pub fn apply_move_events(
+    solution_items: Vec<SolutionItem>,
+    events: impl IntoIterator<Item = MoveEvent>,
+) -> Vec<SolutionItem> {
+    let mut position_to_item: BTreeMap<(i64, i64), SolutionItem> = solution_items
+        .into_iter()
+        .map(|item| ((item.y, item.x), item))
+        .collect();
+    for event in events {
+        position_to_item.insert(
+            (event.y, event.x),
+            SolutionItem {
+                x: event.x,
+                y: event.y,
+                value: event.value,
+                modified_by: event.user_for,
+                revealed: event.revealed,
+                version: event.seq,
+                mark: MarkType::from(event.mark.as_str()),
+                candidates: event.candidates,
+            },
+        );
+    }
+    position_to_item.into_values().collect()
+}
+
+/// Serialises writers to a team's solution until the end of the transaction
+fn lock_solution(conn: &mut PgConnection, crossword_id: &str, team_id: &str) -> QueryResult<usize> {
+    diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind::<Text, _>(format!("{}/{}", crossword_id, team_id))
+        .execute(conn)
+}
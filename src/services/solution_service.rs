@@ -1,69 +1,356 @@
 extern crate futures;
 extern crate serde;
 
-use crate::models::api_models::SolutionItemDto;
-use crate::models::db_models::SolutionItem;
+use crate::formats::grid::solution_grid;
+use crate::models::api_models::{GridScope, MarkType, SolutionItemDto};
+use crate::models::db_models::{MoveEvent, SolutionItem};
 use crate::models::errors::AppError;
-use crate::services::solution_db_actions::{get_solution, store_or_update_solution};
+use crate::services::check_service::{answer_at, cells_in_scope, ensure_released, is_correct};
+use crate::services::crossword_db_actions::get_guardian_crossword_for_id;
+use crate::services::solution_db_actions::{
+    append_checked_move_events, append_revert_events, get_solution, snapshot_solution,
+};
+use crate::services::solve_progress_service::{record_first_move, record_reveal};
 use crate::DbPool;
 use actix_web::web;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+/// How many moves are logged between snapshots of a team's solution
+const SNAPSHOT_INTERVAL: i64 = 50;
+
+/// The moves that were stored, and those rejected for being made against an old version of the
+/// cell along with the cell as it is now
+#[derive(Debug, Clone, Default)]
+pub struct MoveResult {
+    pub applied: Vec<SolutionItemDto>,
+    pub rejected: Vec<SolutionItemDto>,
+}
+
+/// Stores the user's moves. Moves carrying a version are rejected when the cell has been written
+/// since, so two players typing into the same cell can't silently overwrite each other, while
+/// moves without one always apply. Versions are checked under the same lock as the write, so they
+/// can't change in between even with several servers writing to the team's solution
 pub async fn update_solution(
     pool: web::Data<DbPool>,
     solution_items_api: Vec<SolutionItemDto>,
     user_id: String,
     team_id: String,
     crossword_id: String,
-) -> Result<Vec<SolutionItemDto>, AppError> {
-    let current_solution_items = get_solution(pool.clone(), crossword_id.clone(), team_id.clone())
-        .await?
-        .unwrap_or(Vec::new());
-
-    let mut position_to_item: HashMap<(i64, i64), SolutionItem> = current_solution_items
-        .into_iter()
-        .map(|item| ((item.x, item.y), item))
-        .collect();
+) -> Result<MoveResult, AppError> {
+    let modified_by = user_id.clone();
+    let (events, rejected) = append_checked_move_events(
+        pool.clone(),
+        crossword_id.clone(),
+        team_id.clone(),
+        user_id.clone(),
+        move |position_to_item| choose_moves(solution_items_api, position_to_item, &modified_by),
+    )
+    .await?;
+    if events.is_empty() {
+        return Ok(MoveResult {
+            applied: Vec::new(),
+            rejected,
+        });
+    }
+    let applied = finish_moves(pool.clone(), crossword_id.clone(), team_id.clone(), events).await;
+    if is_first_move(&applied) {
+        record_first_move(pool.clone(), team_id.clone(), crossword_id.clone()).await;
+    }
+    Ok(MoveResult { applied, rejected })
+}
 
-    let new_solution_items: Vec<SolutionItem> = solution_items_api
-        .iter()
-        .map(|solution_item| SolutionItem {
+/// Splits the moves into those to store, skipping cells they wouldn't change, and those made
+/// against an old version of the cell, given as the cell is now
+fn choose_moves(
+    solution_items_api: Vec<SolutionItemDto>,
+    position_to_item: &HashMap<(i64, i64), SolutionItem>,
+    user_id: &str,
+) -> (Vec<SolutionItem>, Vec<SolutionItemDto>) {
+    let mut new_solution_items = Vec::new();
+    let mut rejected = Vec::new();
+    for solution_item in solution_items_api {
+        let position = (solution_item.x, solution_item.y);
+        let existing_item = position_to_item.get(&position);
+        let unchanged = existing_item.is_some_and(|item| {
+            item.value == solution_item.value
+                && item.mark == solution_item.mark
+                && item.candidates == solution_item.candidates
+        });
+        if unchanged {
+            continue;
+        }
+        let current_version = existing_item.map_or(0, |item| item.version);
+        if solution_item
+            .version
+            .is_some_and(|version| version != current_version)
+        {
+            rejected.push(existing_item.map_or(
+                SolutionItemDto {
+                    x: solution_item.x,
+                    y: solution_item.y,
+                    version: Some(0),
+                    ..Default::default()
+                },
+                to_solution_item_dto,
+            ));
+            continue;
+        }
+        new_solution_items.push(SolutionItem {
             x: solution_item.x,
             y: solution_item.y,
-            value: solution_item.value.to_owned(),
-            modified_by: user_id.clone(),
+            value: solution_item.value,
+            modified_by: user_id.to_string(),
+            revealed: false,
+            version: current_version,
+            mark: solution_item.mark,
+            candidates: solution_item.candidates,
+        });
+    }
+    (new_solution_items, rejected)
+}
+
+/// Writes the answers into the team's solution for every cell in scope that isn't already right,
+/// flagging them as revealed by the user
+pub async fn reveal_solution(
+    pool: web::Data<DbPool>,
+    scope: GridScope,
+    user_id: String,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Vec<SolutionItemDto>, AppError> {
+    let guardian_crossword =
+        get_guardian_crossword_for_id(pool.clone(), crossword_id.clone()).await?;
+    ensure_released(&guardian_crossword)?;
+    let answers = solution_grid(&guardian_crossword);
+    let width = guardian_crossword.dimensions.cols;
+    let mut cell_answers = Vec::new();
+    for (x, y) in cells_in_scope(&guardian_crossword, &scope)? {
+        cell_answers.push((x, y, answer_at(&answers, width, x, y)?));
+    }
+
+    let revealed_by = user_id.clone();
+    let (events, _) = append_checked_move_events(
+        pool.clone(),
+        crossword_id.clone(),
+        team_id.clone(),
+        user_id.clone(),
+        move |position_to_item| {
+            (
+                reveal_items(cell_answers, position_to_item, &revealed_by),
+                (),
+            )
+        },
+    )
+    .await?;
+    if events.is_empty() {
+        return Ok(Vec::new());
+    }
+    let revealed = finish_moves(pool.clone(), crossword_id.clone(), team_id.clone(), events).await;
+    if is_first_move(&revealed) {
+        record_first_move(pool.clone(), team_id.clone(), crossword_id.clone()).await;
+    }
+    record_reveal(pool.clone(), team_id.clone(), crossword_id.clone()).await;
+    Ok(revealed)
+}
+
+/// Gives the answer for each cell that doesn't already hold it in pen, flagged as revealed by the
+/// user
+fn reveal_items(
+    cell_answers: Vec<(i64, i64, String)>,
+    position_to_item: &HashMap<(i64, i64), SolutionItem>,
+    user_id: &str,
+) -> Vec<SolutionItem> {
+    cell_answers
+        .into_iter()
+        .filter(|(x, y, answer)| {
+            !position_to_item
+                .get(&(*x, *y))
+                .filter(|item| item.mark.is_pen())
+                .is_some_and(|item| is_correct(&item.value.trim().to_uppercase(), answer))
         })
-        .filter(|solution_item| {
-            let position = (solution_item.x, solution_item.y);
-            let existing_item = position_to_item.get(&position);
-            match existing_item {
-                None => true,
-                Some(item) => item.value != solution_item.value,
-            }
+        .map(|(x, y, answer)| SolutionItem {
+            x,
+            y,
+            value: answer,
+            modified_by: user_id.to_string(),
+            revealed: true,
+            ..Default::default()
         })
-        .collect();
+        .collect()
+}
 
-    for solution_item in new_solution_items.clone() {
-        let position = (solution_item.x, solution_item.y);
-        position_to_item.insert(position, solution_item);
-    }
+/// Reverts the user's last move group that hasn't been undone, giving the restored cells
+pub async fn undo_moves(
+    pool: web::Data<DbPool>,
+    user_id: String,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Vec<SolutionItemDto>, AppError> {
+    revert_move_group(pool, user_id, team_id, crossword_id, false).await
+}
+
+/// Reverts the user's last undo, as long as they haven't made a move since
+pub async fn redo_moves(
+    pool: web::Data<DbPool>,
+    user_id: String,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Vec<SolutionItemDto>, AppError> {
+    revert_move_group(pool, user_id, team_id, crossword_id, true).await
+}
 
-    store_or_update_solution(
+/// Puts the cells of the user's latest undoable, or redoable, group back to how they were before
+/// it, skipping cells someone has changed since. Groups where every cell has since changed are
+/// passed over for the one before
+async fn revert_move_group(
+    pool: web::Data<DbPool>,
+    user_id: String,
+    team_id: String,
+    crossword_id: String,
+    redo: bool,
+) -> Result<Vec<SolutionItemDto>, AppError> {
+    let actor = user_id.clone();
+    let reverted = append_revert_events(
         pool.clone(),
         crossword_id.clone(),
         team_id.clone(),
-        position_to_item.into_values().collect(),
+        user_id.clone(),
+        move |group_heads| {
+            let (undoable, redoable) = move_group_stacks(group_heads);
+            let groups = if redo { redoable } else { undoable };
+            groups.into_iter().rev().collect()
+        },
+        move |group, previous, position_to_item| {
+            revert_items(group, previous, position_to_item, &actor)
+        },
     )
     .await?;
-    Ok(new_solution_items
-        .clone()
+    let Some(events) = reverted else {
+        return Err(AppError::BadRequest(format!(
+            "Nothing to {}",
+            if redo { "redo" } else { "undo" }
+        )));
+    };
+    Ok(finish_moves(pool, crossword_id, team_id, events).await)
+}
+
+/// Replays the first moves of the user's groups to find which can be undone and which redone,
+/// latest last. Undoing a group makes its undo redoable, redoing makes the redo undoable, and a new
+/// move clears the redos
+fn move_group_stacks(group_heads: &[MoveEvent]) -> (Vec<i64>, Vec<i64>) {
+    let mut undoable: Vec<i64> = Vec::new();
+    let mut redoable: Vec<i64> = Vec::new();
+    for event in group_heads {
+        match event.reverts {
+            None => {
+                undoable.push(event.group_seq);
+                redoable.clear();
+            }
+            Some(reverted) => {
+                if let Some(index) = undoable.iter().position(|group| *group == reverted) {
+                    undoable.remove(index);
+                    redoable.push(event.group_seq);
+                } else if let Some(index) = redoable.iter().position(|group| *group == reverted) {
+                    redoable.remove(index);
+                    undoable.push(event.group_seq);
+                }
+            }
+        }
+    }
+    (undoable, redoable)
+}
+
+/// Gives each cell of the group that still holds what the group wrote its value from the last move
+/// before the group, credited to whoever wrote it
+fn revert_items(
+    group: &[MoveEvent],
+    previous: &[MoveEvent],
+    position_to_item: &HashMap<(i64, i64), SolutionItem>,
+    user_id: &str,
+) -> Vec<SolutionItem> {
+    let mut group_items: BTreeMap<(i64, i64), &MoveEvent> = BTreeMap::new();
+    for event in group {
+        group_items.insert((event.y, event.x), event);
+    }
+    group_items
+        .into_values()
+        .filter(|event| {
+            position_to_item
+                .get(&(event.x, event.y))
+                .is_some_and(|item| {
+                    item.value == event.value
+                        && item.modified_by == event.user_for
+                        && item.revealed == event.revealed
+                        && item.mark == MarkType::from(event.mark.as_str())
+                        && item.candidates == event.candidates
+                })
+        })
+        .map(|event| {
+            let previous = previous
+                .iter()
+                .find(|previous| previous.x == event.x && previous.y == event.y);
+            SolutionItem {
+                x: event.x,
+                y: event.y,
+                value: previous.map_or(String::new(), |previous| previous.value.clone()),
+                modified_by: previous
+                    .map_or(user_id.to_string(), |previous| previous.user_for.clone()),
+                revealed: previous.is_some_and(|previous| previous.revealed),
+                version: event.seq,
+                mark: previous.map_or(MarkType::Pen, |previous| {
+                    MarkType::from(previous.mark.as_str())
+                }),
+                candidates: previous.map_or(Vec::new(), |previous| previous.candidates.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Takes a snapshot if the moves passed another `SNAPSHOT_INTERVAL`, so the solution can be rebuilt
+/// without replaying the whole log. Gives the moves with their new versions
+async fn finish_moves(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+    events: Vec<MoveEvent>,
+) -> Vec<SolutionItemDto> {
+    if events
         .iter()
-        .map(|solution_item| SolutionItemDto {
-            x: solution_item.x,
-            y: solution_item.y,
-            value: solution_item.clone().value,
+        .any(|event| event.seq % SNAPSHOT_INTERVAL == 0)
+    {
+        if let Err(e) = snapshot_solution(pool, crossword_id, team_id).await {
+            println!("Failed to snapshot solution: {}", e);
+        }
+    }
+    events
+        .into_iter()
+        .map(|event| SolutionItemDto {
+            x: event.x,
+            y: event.y,
+            value: event.value,
+            revealed: event.revealed,
+            version: Some(event.seq),
+            mark: MarkType::from(event.mark.as_str()),
+            candidates: event.candidates,
         })
-        .collect())
+        .collect()
+}
+
+/// Whether the moves start the team's log, the only time there is a first move to record
+fn is_first_move(moves: &[SolutionItemDto]) -> bool {
+    moves.iter().any(|item| item.version == Some(1))
+}
+
+fn to_solution_item_dto(solution_item: &SolutionItem) -> SolutionItemDto {
+    SolutionItemDto {
+        x: solution_item.x,
+        y: solution_item.y,
+        value: solution_item.value.clone(),
+        revealed: solution_item.revealed,
+        version: Some(solution_item.version),
+        mark: solution_item.mark,
+        candidates: solution_item.candidates.clone(),
+    }
 }
 
 pub async fn retrieve_and_send_solution(
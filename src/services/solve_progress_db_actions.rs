@@ -0,0 +1,163 @@
+use actix_web::web;
+use diesel::{ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+
+use crate::models::db_models::SolveProgress;
+use crate::models::errors::AppError;
+use crate::schema::solve_progress::dsl::{
+    active_millis, checks, completed_at, crossword_for, first_move_at, reveals, solve_progress,
+    team_for,
+};
+use crate::DbPool;
+
+/// Records the completion time unless the team has already completed the crossword, returning
+/// whether it was recorded
+pub async fn mark_completed(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+    at: i64,
+) -> actix_web::Result<bool, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        ensure_progress(&mut conn, &crossword_id, &team_id)?;
+        diesel::update(solve_progress.find((crossword_id, team_id)))
+            .filter(completed_at.is_null())
+            .set(completed_at.eq(Some(at)))
+            .execute(&mut conn)
+            .map(|updated| updated > 0)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+/// Records the time of the team's first move on the crossword
+pub async fn mark_first_move(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+    at: i64,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        ensure_progress(&mut conn, &crossword_id, &team_id)?;
+        diesel::update(solve_progress.find((crossword_id, team_id)))
+            .filter(first_move_at.is_null())
+            .set(first_move_at.eq(Some(at)))
+            .execute(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn increment_reveals(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        ensure_progress(&mut conn, &crossword_id, &team_id)?;
+        diesel::update(solve_progress.find((crossword_id, team_id)))
+            .set(reveals.eq(reveals + 1))
+            .execute(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn increment_checks(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        ensure_progress(&mut conn, &crossword_id, &team_id)?;
+        diesel::update(solve_progress.find((crossword_id, team_id)))
+            .set(checks.eq(checks + 1))
+            .execute(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+/// Adds the time between `from` and `to` to the team's active time, ignoring any after they
+/// completed the crossword
+pub async fn add_active_time(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+    from: i64,
+    to: i64,
+) -> actix_web::Result<usize, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        ensure_progress(&mut conn, &crossword_id, &team_id)?;
+        let completed: Option<i64> = solve_progress
+            .find((crossword_id.clone(), team_id.clone()))
+            .select(completed_at)
+            .first(&mut conn)
+            .optional()
+            .map_err(|e| AppError::InternalServerError(e.to_string()))?
+            .flatten();
+        let elapsed = completed.map_or(to, |completed| completed.min(to)) - from;
+        if elapsed <= 0 {
+            return Ok(0);
+        }
+        diesel::update(solve_progress.find((crossword_id, team_id)))
+            .set(active_millis.eq(active_millis + elapsed))
+            .execute(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn get_solve_progress(
+    pool: web::Data<DbPool>,
+    crossword_id: String,
+    team_id: String,
+) -> actix_web::Result<Option<SolveProgress>, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        solve_progress
+            .find((crossword_id, team_id))
+            .first(&mut conn)
+            .optional()
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+pub async fn get_solve_progress_for_team(
+    pool: web::Data<DbPool>,
+    team_id: String,
+) -> actix_web::Result<Vec<SolveProgress>, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    web::block(move || {
+        let mut conn = pool.get()?;
+        solve_progress
+            .filter(team_for.eq(team_id))
+            .load(&mut conn)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))
+    })
+    .await?
+}
+
+fn ensure_progress(
+    conn: &mut PgConnection,
+    crossword_id: &str,
+    team_id: &str,
+) -> Result<usize, AppError> {
+    diesel::insert_into(solve_progress)
+        .values((crossword_for.eq(crossword_id), team_for.eq(team_id)))
+        .on_conflict((crossword_for, team_for))
+        .do_nothing()
+        .execute(conn)
+        .map_err(|e| AppError::InternalServerError(e.to_string()))
+}
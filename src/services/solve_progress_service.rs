@@ -0,0 +1,220 @@
+use actix_web::web;
+use chrono::Utc;
+use itertools::Itertools;
+use std::collections::HashMap;
+
+use crate::formats::grid::solution_grid;
+use crate::models::api_models::{GroupStats, PuzzleStats, TeamStats};
+use crate::models::db_models::Crossword;
+use crate::models::errors::AppError;
+use crate::models::guardian::GuardianCrossword;
+use crate::services::check_service::is_correct;
+use crate::services::crossword_db_actions::{
+    get_crosswords_for_ids, get_guardian_crossword_for_id,
+};
+use crate::services::crossword_service::solution_released;
+use crate::services::solution_db_actions::get_solution;
+use crate::services::solve_progress_db_actions::{
+    add_active_time, get_solve_progress, get_solve_progress_for_team, increment_checks,
+    increment_reveals, mark_completed, mark_first_move,
+};
+use crate::DbPool;
+
+/// Records when a team first has every white cell filled in correctly, giving the completion time
+/// when it is this call that notices. Completing a prize crossword early would give away that the
+/// answers are right, so it is recorded without giving the time until the answers are released
+pub async fn record_completion(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Option<i64>, AppError> {
+    let progress = get_solve_progress(pool.clone(), crossword_id.clone(), team_id.clone()).await?;
+    if progress.is_some_and(|progress| progress.completed_at.is_some()) {
+        return Ok(None);
+    }
+    let guardian_crossword =
+        get_guardian_crossword_for_id(pool.clone(), crossword_id.clone()).await?;
+    let width = guardian_crossword.dimensions.cols;
+    let entered: HashMap<(i64, i64), String> =
+        get_solution(pool.clone(), crossword_id.clone(), team_id.clone())
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| item.mark.is_pen())
+            .map(|item| ((item.x, item.y), item.value.trim().to_uppercase()))
+            .collect();
+    let completed = solution_grid(&guardian_crossword)
+        .iter()
+        .enumerate()
+        .all(|(index, answer)| match answer {
+            None => true,
+            Some(answer) => entered
+                .get(&(index as i64 % width, index as i64 / width))
+                .is_some_and(|value| !answer.is_empty() && is_correct(value, answer)),
+        });
+    if !completed {
+        return Ok(None);
+    }
+    let now = Utc::now().timestamp_millis();
+    let recorded = mark_completed(pool, crossword_id, team_id, now).await?;
+    Ok((recorded && solution_released(&guardian_crossword)).then_some(now))
+}
+
+/// Gives when the team completed the crossword, once its answers are released
+pub async fn get_released_completion(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+) -> Result<Option<i64>, AppError> {
+    let completed = get_solve_progress(pool.clone(), crossword_id.clone(), team_id)
+        .await?
+        .and_then(|progress| progress.completed_at);
+    let Some(completed) = completed else {
+        return Ok(None);
+    };
+    let guardian_crossword = get_guardian_crossword_for_id(pool, crossword_id).await?;
+    Ok(solution_released(&guardian_crossword).then_some(completed))
+}
+
+/// Stats are recorded alongside moves, a failure to record is logged rather than failing the move
+pub async fn record_first_move(pool: web::Data<DbPool>, team_id: String, crossword_id: String) {
+    let now = Utc::now().timestamp_millis();
+    if let Err(e) = mark_first_move(pool, crossword_id, team_id, now).await {
+        println!("Could not record first move: {}", e);
+    }
+}
+
+pub async fn record_reveal(pool: web::Data<DbPool>, team_id: String, crossword_id: String) {
+    if let Err(e) = increment_reveals(pool, crossword_id, team_id).await {
+        println!("Could not record reveal: {}", e);
+    }
+}
+
+pub async fn record_check(pool: web::Data<DbPool>, team_id: String, crossword_id: String) {
+    if let Err(e) = increment_checks(pool, crossword_id, team_id).await {
+        println!("Could not record check: {}", e);
+    }
+}
+
+pub async fn record_active_time(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    crossword_id: String,
+    from: i64,
+    to: i64,
+) {
+    if let Err(e) = add_active_time(pool, crossword_id, team_id, from, to).await {
+        println!("Could not record active time: {}", e);
+    }
+}
+
+/// Gives the team's stats, adding the time since `active_since` for crosswords the team is
+/// connected to that hasn't been stored yet
+pub async fn get_team_stats(
+    pool: web::Data<DbPool>,
+    team_id: String,
+    active_since: HashMap<String, i64>,
+) -> Result<TeamStats, AppError> {
+    let now = Utc::now().timestamp_millis();
+    let progress = get_solve_progress_for_team(pool.clone(), team_id.clone()).await?;
+    let crosswords: HashMap<String, Crossword> = get_crosswords_for_ids(
+        pool,
+        progress
+            .iter()
+            .map(|progress| progress.crossword_for.clone())
+            .collect(),
+    )
+    .await?
+    .into_iter()
+    .map(|crossword| (crossword.id.clone(), crossword))
+    .collect();
+    let mut puzzles: Vec<PuzzleStats> = progress
+        .into_iter()
+        .filter_map(|progress| {
+            let crossword = crosswords.get(&progress.crossword_for)?;
+            let guardian_crossword =
+                serde_json::from_value::<GuardianCrossword>(crossword.crossword_json.clone()).ok();
+            let setter = guardian_crossword
+                .as_ref()
+                .map(|guardian_crossword| guardian_crossword.creator.name.clone())
+                .unwrap_or_default();
+            let unstored_active_millis =
+                active_since
+                    .get(&progress.crossword_for)
+                    .map_or(0, |since| {
+                        let until = progress
+                            .completed_at
+                            .map_or(now, |completed| completed.min(now));
+                        (until - since).max(0)
+                    });
+            // completions of unreleased prize crosswords stay hidden until the answers are out
+            let completed_at = progress
+                .completed_at
+                .filter(|_| guardian_crossword.as_ref().is_some_and(solution_released));
+            Some(PuzzleStats {
+                crossword_id: progress.crossword_for,
+                series: crossword.series.clone(),
+                series_no: crossword.series_no,
+                setter,
+                first_move_at: progress.first_move_at,
+                completed_at,
+                solve_millis: progress
+                    .first_move_at
+                    .zip(completed_at)
+                    .map(|(first_move, completed)| completed - first_move),
+                active_millis: progress.active_millis + unstored_active_millis,
+                reveals: progress.reveals,
+                checks: progress.checks,
+            })
+        })
+        .collect();
+    puzzles.sort_by_key(|puzzle| std::cmp::Reverse(puzzle.first_move_at));
+    Ok(TeamStats {
+        team: team_id,
+        by_series: group_stats(&puzzles, |puzzle| puzzle.series.clone()),
+        by_setter: group_stats(&puzzles, |puzzle| puzzle.setter.clone()),
+        puzzles,
+    })
+}
+
+fn group_stats(puzzles: &[PuzzleStats], key: impl Fn(&PuzzleStats) -> String) -> Vec<GroupStats> {
+    puzzles
+        .iter()
+        .into_group_map_by(|puzzle| key(puzzle))
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(name, group)| {
+            let completed: Vec<&&PuzzleStats> = group
+                .iter()
+                .filter(|puzzle| puzzle.completed_at.is_some())
+                .collect();
+            GroupStats {
+                name,
+                started: group.len() as i64,
+                completed: completed.len() as i64,
+                median_solve_millis: median(
+                    completed
+                        .iter()
+                        .filter_map(|puzzle| puzzle.solve_millis)
+                        .collect(),
+                ),
+                median_active_millis: median(
+                    completed
+                        .iter()
+                        .map(|puzzle| puzzle.active_millis)
+                        .collect(),
+                ),
+            }
+        })
+        .collect()
+}
+
+fn median(mut values: Vec<i64>) -> Option<i64> {
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    match values.len() {
+        0 => None,
+        length if length % 2 == 0 => Some((values[middle - 1] + values[middle]) / 2),
+        _ => Some(values[middle]),
+    }
+}
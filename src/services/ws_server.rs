@@ -1,17 +1,32 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
-use crate::models::api_models::SolutionItemDto;
-use crate::services::solution_service::{retrieve_and_send_solution, update_solution};
+use crate::models::api_models::{
+    ClueId, ClueNoteUpdate, GridScope, Replay, SolutionItemDto, WsResponse,
+};
+use crate::models::errors::AppError;
+use crate::services::chat_service::{get_chat_history, send_chat_message};
+use crate::services::check_service::check_solution;
+use crate::services::clue_note_service::{get_notes, set_note};
+use crate::services::replay_service::get_replay;
+use crate::services::solution_service::{
+    redo_moves, retrieve_and_send_solution, reveal_solution, undo_moves, update_solution,
+};
+use crate::services::solve_progress_service::{
+    get_released_completion, record_active_time, record_completion,
+};
 use crate::services::ws_session;
 use crate::services::ws_session::WsSession;
 use crate::DbPool;
 use actix::prelude::*;
 use actix_web::web::Data;
+use chrono::Utc;
+use serde::Serialize;
 use uuid::Uuid;
 
-/// New chat session is created
+/// New chat session is created, replying with the messages to start the session with
 #[derive(Message, Debug, Clone)]
-#[rtype(String)]
+#[rtype(result = "Vec<String>")]
 pub struct Connect {
     pub session: WsSession,
     pub addr: Addr<WsSession>,
@@ -31,9 +46,74 @@ pub struct Move {
     pub sender: WsSession,
 }
 
+/// Checks the sender's team solution, replying only to the sender
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Check {
+    pub scope: GridScope,
+    pub sender: WsSession,
+}
+
+/// Reveals answers in a team's solution, broadcasting them like moves
+#[derive(Message)]
+#[rtype(result = "Result<Vec<SolutionItemDto>, AppError>")]
+pub struct Reveal {
+    pub scope: GridScope,
+    pub user: String,
+    pub team: String,
+    pub crossword: String,
+}
+
+/// Undoes or redoes a user's last move group, broadcasting the restored cells like moves
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Revert {
+    pub user: String,
+    pub redo: bool,
+    pub sender: WsSession,
+}
+
+/// Replaces a note on a clue, sending it to the whole team
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetNote {
+    pub update: ClueNoteUpdate,
+    pub sender: WsSession,
+}
+
+/// Sends a chat message to the whole team
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Chat {
+    pub text: String,
+    pub clue_id: Option<ClueId>,
+    pub sender: WsSession,
+}
+
+/// Fetches a team's moves for a session to replay
+#[derive(Message)]
+#[rtype(result = "Result<Replay, AppError>")]
+pub struct GetReplay {
+    pub team: String,
+    pub crossword: String,
+}
+
+/// Gives when each crossword the team has a session connected to last had its active time stored
+#[derive(Message)]
+#[rtype(result = "HashMap<String, i64>")]
+pub struct GetActiveSince {
+    pub team: String,
+}
+
+/// How often the active time of connected teams is stored
+const ACTIVE_TIME_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Clone, Debug)]
 pub struct MoveServer {
     sessions: HashMap<Uuid, Connect>,
+    /// When each team and crossword with a connected session last had its active time stored, or
+    /// was first connected to
+    active_since: HashMap<(String, String), i64>,
     pool: DbPool,
 }
 
@@ -41,21 +121,72 @@ impl MoveServer {
     pub fn new(pool: DbPool) -> MoveServer {
         MoveServer {
             sessions: HashMap::new(),
+            active_since: HashMap::new(),
             pool,
         }
     }
 }
 
 impl MoveServer {
-    fn broadcast_moves(&self, sender: WsSession, solution_items: Vec<SolutionItemDto>) {
-        for session in self.sessions.clone().into_iter() {
-            let ws_session = session.1.session;
-            if ws_session.crossword == sender.crossword && ws_session.team == sender.team {
-                let result = serde_json::to_string(&solution_items);
-                match result {
-                    Ok(message) => session.1.addr.do_send(ws_session::Message(message)),
-                    Err(e) => println!("{}", e.to_string()),
-                }
+    fn broadcast_moves(&self, team: &str, crossword: &str, solution_items: Vec<SolutionItemDto>) {
+        self.broadcast(team, crossword, &solution_items);
+    }
+
+    fn broadcast<T: Serialize>(&self, team: &str, crossword: &str, message: &T) {
+        let message = match serde_json::to_string(message) {
+            Ok(message) => message,
+            Err(e) => return println!("{}", e),
+        };
+        for session in self.sessions.values() {
+            let ws_session = &session.session;
+            if ws_session.crossword == crossword && ws_session.team == team {
+                session.addr.do_send(ws_session::Message(message.clone()));
+            }
+        }
+    }
+
+    /// Sends stored moves to the team and checks whether they completed the crossword
+    fn apply_moves(&self, team: &str, crossword: &str, solution_items: Vec<SolutionItemDto>) {
+        self.broadcast_moves(team, crossword, solution_items);
+        self.notify_completion(team, crossword);
+    }
+
+    /// Tells the team when their latest change completed the crossword
+    fn notify_completion(&self, team: &str, crossword: &str) {
+        let result = futures::executor::block_on(record_completion(
+            Data::new(self.pool.clone()),
+            team.to_string(),
+            crossword.to_string(),
+        ));
+        match result {
+            Ok(Some(completed_at)) => {
+                self.broadcast(team, crossword, &WsResponse::Completed { completed_at })
+            }
+            Ok(None) => (),
+            Err(e) => println!("Could not check completion: {}", e),
+        }
+    }
+
+    /// Stores the active time of every connected team so far, so it isn't lost if the server stops
+    fn flush_active_time(&mut self) {
+        let now = Utc::now().timestamp_millis();
+        for ((team, crossword), since) in self.active_since.iter_mut() {
+            futures::executor::block_on(record_active_time(
+                Data::new(self.pool.clone()),
+                team.clone(),
+                crossword.clone(),
+                *since,
+                now,
+            ));
+            *since = now;
+        }
+    }
+
+    fn send_to_session(&self, id: &Uuid, response: WsResponse) {
+        if let Some(connect) = self.sessions.get(id) {
+            match serde_json::to_string(&response) {
+                Ok(message) => connect.addr.do_send(ws_session::Message(message)),
+                Err(e) => println!("{}", e),
             }
         }
     }
@@ -63,23 +194,73 @@ impl MoveServer {
 
 impl Actor for MoveServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(ACTIVE_TIME_FLUSH_INTERVAL, |act, _| act.flush_active_time());
+    }
 }
 
 impl Handler<Connect> for MoveServer {
-    type Result = String;
+    type Result = Vec<String>;
 
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
-        println!("Someone joined: {}", msg.session.id.to_string());
+        println!("Someone joined: {}", msg.session.id);
         self.sessions.insert(msg.session.id, msg.clone());
+        self.active_since
+            .entry((msg.session.team.clone(), msg.session.crossword.clone()))
+            .or_insert_with(|| Utc::now().timestamp_millis());
         let result = futures::executor::block_on(retrieve_and_send_solution(
             Data::new(self.pool.clone()),
             msg.session.team.clone(),
             msg.session.crossword.clone(),
         ));
-        return match result {
+        let solution = match result {
             Ok(m) => m,
             Err(e) => e.to_string(),
         };
+        let notes = futures::executor::block_on(get_notes(
+            Data::new(self.pool.clone()),
+            msg.session.team.clone(),
+            msg.session.crossword.clone(),
+        ));
+        let notes = match notes {
+            Ok(notes) => WsResponse::Notes { notes },
+            Err(e) => WsResponse::Error {
+                message: e.to_string(),
+            },
+        };
+        let chat = futures::executor::block_on(get_chat_history(
+            Data::new(self.pool.clone()),
+            msg.session.team.clone(),
+            msg.session.crossword.clone(),
+        ));
+        let chat = match chat {
+            Ok(messages) => WsResponse::ChatHistory { messages },
+            Err(e) => WsResponse::Error {
+                message: e.to_string(),
+            },
+        };
+        let completion = futures::executor::block_on(get_released_completion(
+            Data::new(self.pool.clone()),
+            msg.session.team.clone(),
+            msg.session.crossword.clone(),
+        ));
+        let completion = match completion {
+            Ok(completed_at) => {
+                completed_at.map(|completed_at| WsResponse::Completed { completed_at })
+            }
+            Err(e) => Some(WsResponse::Error {
+                message: e.to_string(),
+            }),
+        };
+        let mut messages = vec![solution];
+        for response in [Some(notes), Some(chat), completion].into_iter().flatten() {
+            match serde_json::to_string(&response) {
+                Ok(response) => messages.push(response),
+                Err(e) => println!("{}", e),
+            }
+        }
+        messages
     }
 }
 
@@ -87,7 +268,40 @@ impl Handler<Disconnect> for MoveServer {
     type Result = ();
 
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        self.sessions.remove(&msg.id);
+        let Some(connect) = self.sessions.remove(&msg.id) else {
+            return;
+        };
+        let (team, crossword) = (connect.session.team, connect.session.crossword);
+        let still_connected = self
+            .sessions
+            .values()
+            .any(|other| other.session.team == team && other.session.crossword == crossword);
+        if still_connected {
+            return;
+        }
+        if let Some(since) = self.active_since.remove(&(team.clone(), crossword.clone())) {
+            futures::executor::block_on(record_active_time(
+                Data::new(self.pool.clone()),
+                team,
+                crossword,
+                since,
+                Utc::now().timestamp_millis(),
+            ));
+        }
+    }
+}
+
+impl Handler<GetActiveSince> for MoveServer {
+    type Result = MessageResult<GetActiveSince>;
+
+    fn handle(&mut self, msg: GetActiveSince, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.active_since
+                .iter()
+                .filter(|((team, _), _)| *team == msg.team)
+                .map(|((_, crossword), since)| (crossword.clone(), *since))
+                .collect(),
+        )
     }
 }
 
@@ -103,18 +317,158 @@ impl Handler<Move> for MoveServer {
             msg.sender.crossword.clone(),
         ));
         match result {
-            Ok(moves) => {
-                self.broadcast_moves(msg.sender.clone(), moves);
+            Ok(result) => {
+                if !result.rejected.is_empty() {
+                    self.send_to_session(
+                        &msg.sender.id,
+                        WsResponse::Rejected {
+                            moves: result.rejected,
+                        },
+                    );
+                }
+                if result.applied.is_empty() {
+                    return;
+                }
+                self.apply_moves(&msg.sender.team, &msg.sender.crossword, result.applied);
             }
             Err(e) => {
-                self.sessions.get(&msg.sender.id).map(|connect| {
-                    connect.addr.do_send(ws_session::Message(format!(
-                        "Error saving moves: {}",
-                        e.to_string()
-                    )))
-                });
-                ()
+                if let Some(connect) = self.sessions.get(&msg.sender.id) {
+                    connect
+                        .addr
+                        .do_send(ws_session::Message(format!("Error saving moves: {}", e)))
+                }
+            }
+        };
+    }
+}
+
+impl Handler<Check> for MoveServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Check, _: &mut Context<Self>) {
+        let result = futures::executor::block_on(check_solution(
+            Data::new(self.pool.clone()),
+            msg.sender.team.clone(),
+            msg.sender.crossword.clone(),
+            msg.scope,
+        ));
+        let response = match result {
+            Ok(check_result) => WsResponse::CheckResult(check_result),
+            Err(e) => WsResponse::Error {
+                message: e.to_string(),
             },
         };
+        self.send_to_session(&msg.sender.id, response);
+    }
+}
+
+impl Handler<Reveal> for MoveServer {
+    type Result = Result<Vec<SolutionItemDto>, AppError>;
+
+    fn handle(&mut self, msg: Reveal, _: &mut Context<Self>) -> Self::Result {
+        let revealed = futures::executor::block_on(reveal_solution(
+            Data::new(self.pool.clone()),
+            msg.scope,
+            msg.user,
+            msg.team.clone(),
+            msg.crossword.clone(),
+        ))?;
+        if revealed.is_empty() {
+            return Ok(revealed);
+        }
+        self.apply_moves(&msg.team, &msg.crossword, revealed.clone());
+        Ok(revealed)
+    }
+}
+
+impl Handler<GetReplay> for MoveServer {
+    type Result = Result<Replay, AppError>;
+
+    fn handle(&mut self, msg: GetReplay, _: &mut Context<Self>) -> Self::Result {
+        futures::executor::block_on(get_replay(
+            Data::new(self.pool.clone()),
+            msg.team,
+            msg.crossword,
+        ))
+    }
+}
+
+impl Handler<Revert> for MoveServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Revert, _: &mut Context<Self>) {
+        let (team, crossword) = (msg.sender.team.clone(), msg.sender.crossword.clone());
+        let pool = Data::new(self.pool.clone());
+        let result = futures::executor::block_on(async {
+            if msg.redo {
+                redo_moves(pool, msg.user, team.clone(), crossword.clone()).await
+            } else {
+                undo_moves(pool, msg.user, team.clone(), crossword.clone()).await
+            }
+        });
+        match result {
+            Ok(moves) => self.apply_moves(&team, &crossword, moves),
+            Err(e) => self.send_to_session(
+                &msg.sender.id,
+                WsResponse::Error {
+                    message: e.to_string(),
+                },
+            ),
+        }
+    }
+}
+
+impl Handler<SetNote> for MoveServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetNote, _: &mut Context<Self>) {
+        let result = futures::executor::block_on(set_note(
+            Data::new(self.pool.clone()),
+            msg.update,
+            msg.sender.user.clone(),
+            msg.sender.team.clone(),
+            msg.sender.crossword.clone(),
+        ));
+        match result {
+            Ok(note) => self.broadcast(
+                &msg.sender.team,
+                &msg.sender.crossword,
+                &WsResponse::Note(note),
+            ),
+            Err(e) => self.send_to_session(
+                &msg.sender.id,
+                WsResponse::Error {
+                    message: e.to_string(),
+                },
+            ),
+        }
+    }
+}
+
+impl Handler<Chat> for MoveServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Chat, _: &mut Context<Self>) {
+        let result = futures::executor::block_on(send_chat_message(
+            Data::new(self.pool.clone()),
+            msg.text,
+            msg.clue_id,
+            msg.sender.user.clone(),
+            msg.sender.team.clone(),
+            msg.sender.crossword.clone(),
+        ));
+        match result {
+            Ok(message) => self.broadcast(
+                &msg.sender.team,
+                &msg.sender.crossword,
+                &WsResponse::Chat(message),
+            ),
+            Err(e) => self.send_to_session(
+                &msg.sender.id,
+                WsResponse::Error {
+                    message: e.to_string(),
+                },
+            ),
+        }
     }
 }
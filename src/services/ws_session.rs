@@ -5,9 +5,14 @@ use actix::prelude::*;
 use actix_web_actors::ws;
 use actix_web_actors::ws::WebsocketContext;
 
-use crate::models::api_models::SolutionItemDto;
+use crate::models::api_models::{GridScope, SolutionItemDto, WsCommand, WsResponse};
+use crate::models::errors::AppError;
+use crate::services::replay_service::replay_frames;
 use crate::services::ws_server;
-use crate::services::ws_server::{Move, MoveServer};
+use crate::services::ws_server::{
+    Chat, Check, GetReplay, Move, MoveServer, Reveal, Revert, SetNote,
+};
+use serde::Serialize;
 use uuid::Uuid;
 
 /// Chat server sends this messages to session
@@ -23,6 +28,10 @@ pub struct WsSession {
     pub user: String,
     pub team: String,
     pub crossword: String,
+    /// Whether the connection was opened with the admin token
+    pub admin: bool,
+    /// The pending frames of a replay in progress
+    pub replay_handles: Vec<SpawnHandle>,
 }
 
 impl Actor for WsSession {
@@ -40,8 +49,10 @@ impl Actor for WsSession {
             .into_actor(self)
             .then(|res, _, ctx| {
                 match res {
-                    Ok(m) => {
-                        ctx.text(m);
+                    Ok(messages) => {
+                        for m in messages {
+                            ctx.text(m);
+                        }
                     }
                     _ => ctx.stop(),
                 }
@@ -78,15 +89,37 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
             Ok(ws::Message::Nop) => (),
             Ok(ws::Message::Text(s)) => {
                 let value: Result<Vec<SolutionItemDto>, _> = serde_json::from_str(s.borrow());
-                match value {
-                    Ok(solution_items) => self.server_addr.do_send(Move {
+                if let Ok(solution_items) = value {
+                    self.server_addr.do_send(Move {
                         solution_items,
                         sender: self.clone(),
+                    });
+                    return;
+                }
+                let command: Result<WsCommand, _> = serde_json::from_str(s.borrow());
+                match command {
+                    Ok(WsCommand::Check(scope)) => self.server_addr.do_send(Check {
+                        scope,
+                        sender: self.clone(),
+                    }),
+                    Ok(WsCommand::Reveal(scope)) => self.reveal(scope, ctx),
+                    Ok(WsCommand::Replay { speed }) => self.replay(speed.unwrap_or(1.0), ctx),
+                    Ok(WsCommand::StopReplay) => self.stop_replay(ctx),
+                    Ok(WsCommand::Undo { user }) => self.revert(user, false, ctx),
+                    Ok(WsCommand::Redo { user }) => self.revert(user, true, ctx),
+                    Ok(WsCommand::SetNote(update)) => self.server_addr.do_send(SetNote {
+                        update,
+                        sender: self.clone(),
+                    }),
+                    Ok(WsCommand::Chat { text, clue_id }) => self.server_addr.do_send(Chat {
+                        text,
+                        clue_id,
+                        sender: self.clone(),
                     }),
-                    Err(e) => println!("{}", e.to_string()),
+                    Err(e) => println!("{}", e),
                 }
             }
-            Err(e) => println!("{}", e.to_string()),
+            Err(e) => println!("{}", e),
         }
     }
 }
@@ -100,7 +133,13 @@ impl Handler<Message> for WsSession {
 }
 
 impl WsSession {
-    pub fn new(addr: Addr<MoveServer>, user: String, team: String, crossword: String) -> WsSession {
+    pub fn new(
+        addr: Addr<MoveServer>,
+        user: String,
+        team: String,
+        crossword: String,
+        admin: bool,
+    ) -> WsSession {
         WsSession {
             id: Uuid::new_v4(),
             server_addr: addr,
@@ -108,6 +147,110 @@ impl WsSession {
             user,
             team,
             crossword,
+            admin,
+            replay_handles: Vec::new(),
+        }
+    }
+
+    /// Revealed letters reach every session through the broadcast, only errors are sent here
+    fn reveal(&self, scope: GridScope, ctx: &mut WebsocketContext<WsSession>) {
+        self.server_addr
+            .send(Reveal {
+                scope,
+                user: self.user.clone(),
+                team: self.team.clone(),
+                crossword: self.crossword.clone(),
+            })
+            .into_actor(self)
+            .then(|res, _, ctx| {
+                let error = match res {
+                    Ok(Ok(_)) => None,
+                    Ok(Err(e)) => Some(e.to_string()),
+                    Err(e) => Some(e.to_string()),
+                };
+                if let Some(message) = error {
+                    match serde_json::to_string(&WsResponse::Error { message }) {
+                        Ok(response) => ctx.text(response),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    /// Only admins can undo or redo another user's moves
+    fn revert(&self, user: Option<String>, redo: bool, ctx: &mut WebsocketContext<WsSession>) {
+        let user = user.unwrap_or_else(|| self.user.clone());
+        if user != self.user && !self.admin {
+            send_response(
+                ctx,
+                &WsResponse::Error {
+                    message: AppError::Unauthorized.to_string(),
+                },
+            );
+            return;
+        }
+        self.server_addr.do_send(Revert {
+            user,
+            redo,
+            sender: self.clone(),
+        });
+    }
+
+    /// Streams the team's moves back to this session only, in the same shape as live moves,
+    /// replacing any replay already in progress
+    fn replay(&mut self, speed: f64, ctx: &mut WebsocketContext<WsSession>) {
+        self.stop_replay(ctx);
+        self.server_addr
+            .send(GetReplay {
+                team: self.team.clone(),
+                crossword: self.crossword.clone(),
+            })
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                let frames = match res {
+                    Ok(Ok(replay)) => replay_frames(&replay.moves, speed)
+                        .map(|frames| (replay.moves.len(), frames)),
+                    Ok(Err(e)) => Err(e),
+                    Err(e) => Err(AppError::InternalServerError(e.to_string())),
+                };
+                let (moves, frames) = match frames {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        send_response(
+                            ctx,
+                            &WsResponse::Error {
+                                message: e.to_string(),
+                            },
+                        );
+                        return fut::ready(());
+                    }
+                };
+                send_response(ctx, &WsResponse::ReplayStarted { moves });
+                if frames.is_empty() {
+                    send_response(ctx, &WsResponse::ReplayFinished);
+                }
+                let frame_count = frames.len();
+                for (index, (offset, solution_items)) in frames.into_iter().enumerate() {
+                    let last = index + 1 == frame_count;
+                    act.replay_handles
+                        .push(ctx.run_later(offset, move |act, ctx| {
+                            send_response(ctx, &solution_items);
+                            if last {
+                                act.replay_handles.clear();
+                                send_response(ctx, &WsResponse::ReplayFinished);
+                            }
+                        }));
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stop_replay(&mut self, ctx: &mut WebsocketContext<WsSession>) {
+        for handle in self.replay_handles.drain(..) {
+            ctx.cancel_future(handle);
         }
     }
 
@@ -125,3 +268,10 @@ impl WsSession {
         });
     }
 }
+
+fn send_response<T: Serialize>(ctx: &mut WebsocketContext<WsSession>, response: &T) {
+    match serde_json::to_string(response) {
+        Ok(response) => ctx.text(response),
+        Err(e) => println!("{}", e),
+    }
+}